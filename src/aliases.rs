@@ -0,0 +1,80 @@
+// Alternative names for tracks and cars, so sheets can use the names people actually use ("Nordschleife", "M4")
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{read_toml_or_json, Result};
+
+// The keys are normalized, see track_key and car_key
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Aliases {
+    // alias -> track id
+    #[serde(default)]
+    pub tracks: BTreeMap<String, String>,
+    // alias -> car model id
+    #[serde(default)]
+    pub cars: BTreeMap<String, u32>,
+}
+
+impl Aliases {
+    /// Loads an aliases file (.toml, otherwise json).
+    /// Use Catalog::add_aliases to check and use them.
+    pub fn load(path: &PathBuf) -> Result<Aliases> {
+        info!("Loading aliases {}", path.display());
+        read_toml_or_json(path)
+    }
+
+    /// Adds the other aliases, replacing existing ones with the same name
+    pub fn merge(&mut self, other: Aliases) {
+        for (alias, track) in other.tracks {
+            self.tracks.insert(track_key(&alias), track_key(&track));
+        }
+        for (alias, id) in other.cars {
+            self.cars.insert(car_key(&alias), id);
+        }
+    }
+
+    /// The track id the alias stands for
+    pub fn track(&self, alias: &str) -> Option<&str> {
+        self.tracks.get(&track_key(alias)).map(String::as_str)
+    }
+
+    /// The car model id the alias stands for
+    pub fn car(&self, alias: &str) -> Option<u32> {
+        self.cars.get(&car_key(alias)).copied()
+    }
+
+    /// All aliases of the track
+    pub fn track_aliases(&self, track: &str) -> Vec<&str> {
+        self.tracks
+            .iter()
+            .filter(|(_, target)| target.as_str() == track)
+            .map(|(alias, _)| alias.as_str())
+            .collect()
+    }
+
+    /// All aliases of the car
+    pub fn car_aliases(&self, id: u32) -> Vec<&str> {
+        self.cars
+            .iter()
+            .filter(|(_, target)| **target == id)
+            .map(|(alias, _)| alias.as_str())
+            .collect()
+    }
+}
+
+// Tracks are compared like track ids, lowercase with underscores
+pub(crate) fn track_key(alias: &str) -> String {
+    alias.trim().replace(' ', "_").to_lowercase()
+}
+
+// Car aliases are compared ignoring case and repeated spaces
+fn car_key(alias: &str) -> String {
+    alias
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
@@ -0,0 +1,338 @@
+// The known tracks and cars, loaded from a file so a new ACC DLC doesn't need a new release
+
+use std::{collections::HashSet, fmt, path::PathBuf, str::FromStr};
+
+use log::{info, trace};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aliases::{self, Aliases},
+    fuzzy, read_toml_or_json, Error, Result,
+};
+
+// The catalog matching the ACC version of this release
+const BUILTIN: &str = include_str!("catalog.json");
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Catalog {
+    // The ACC version the catalog is made for, like "1.9"
+    pub version: String,
+    // These are the multiplayer tracknames,
+    // different from the randomly captialized tracknames from the setup folders
+    pub tracks: Vec<String>,
+    // In the order the name lookup uses, see find_car_models
+    pub cars: Vec<Car>,
+    #[serde(default)]
+    pub aliases: Aliases,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Car {
+    pub id: u32,
+    pub name: String,
+    pub class: CarClass,
+}
+
+// Written like in the car names, "GT3"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CarClass {
+    Gt3,
+    Gt4,
+    Gt2,
+    // The one make cup cars, like the Porsche 992 GT3 Cup
+    Gtc,
+    Tcx,
+}
+
+impl CarClass {
+    pub const ALL: [CarClass; 5] = [
+        CarClass::Gt3,
+        CarClass::Gt4,
+        CarClass::Gt2,
+        CarClass::Gtc,
+        CarClass::Tcx,
+    ];
+
+    /// The class written like "GT4", ignoring case
+    pub fn from_name(name: &str) -> Option<CarClass> {
+        CarClass::ALL
+            .into_iter()
+            .find(|class| class.to_string().eq_ignore_ascii_case(name.trim()))
+    }
+}
+
+impl fmt::Display for CarClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CarClass::Gt3 => "GT3",
+            CarClass::Gt4 => "GT4",
+            CarClass::Gt2 => "GT2",
+            CarClass::Gtc => "GTC",
+            CarClass::Tcx => "TCX",
+        })
+    }
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        serde_json::from_str(BUILTIN).expect("The built-in catalog is invalid")
+    }
+}
+
+impl Catalog {
+    /// Loads a catalog file (.toml, otherwise json), replacing the built-in catalog completely
+    pub fn load(path: &PathBuf) -> Result<Catalog> {
+        let mut catalog: Catalog = read_toml_or_json(path)?;
+        info!(
+            "Loaded catalog {} for ACC {} ({} tracks, {} cars)",
+            path.display(),
+            catalog.version,
+            catalog.tracks.len(),
+            catalog.cars.len()
+        );
+
+        let invalid = |reason: String| Error::InvalidCatalog {
+            path: path.clone(),
+            reason,
+        };
+        let mut tracks = HashSet::new();
+        for track in catalog.tracks.iter_mut() {
+            *track = aliases::track_key(track);
+            if !tracks.insert(track.clone()) {
+                return Err(invalid(format!("track {} is listed twice", track)));
+            }
+        }
+        let mut ids = HashSet::new();
+        for car in catalog.cars.iter() {
+            if !ids.insert(car.id) {
+                return Err(invalid(format!("car model id {} is listed twice", car.id)));
+            }
+        }
+
+        // The aliases of the file are checked like user aliases
+        let aliases = std::mem::take(&mut catalog.aliases);
+        catalog.add_aliases(aliases)?;
+        Ok(catalog)
+    }
+
+    /// Adds the aliases, replacing existing ones with the same name.
+    /// Returns Error::InvalidAlias if an alias points at a track or car that isn't in the catalog.
+    pub fn add_aliases(&mut self, other: Aliases) -> Result<()> {
+        for (alias, track) in other.tracks.iter() {
+            if !self.tracks.contains(&aliases::track_key(track)) {
+                return Err(Error::InvalidAlias {
+                    alias: alias.clone(),
+                    target: track.clone(),
+                });
+            }
+        }
+        for (alias, id) in other.cars.iter() {
+            if self.get_car_name_from_id(*id).is_none() {
+                return Err(Error::InvalidAlias {
+                    alias: alias.clone(),
+                    target: id.to_string(),
+                });
+            }
+        }
+
+        self.aliases.merge(other);
+        Ok(())
+    }
+
+    pub fn validate_track(&self, track_str: &str) -> Option<String> {
+        if let Some(track) = self.aliases.track(track_str) {
+            trace!("Found Track {} (alias {})", track, track_str.trim());
+            return Some(track.to_string());
+        }
+
+        let track_str = aliases::track_key(track_str);
+
+        for item in self.tracks.iter() {
+            if item.eq_ignore_ascii_case(track_str.as_str()) {
+                trace!("Found Track {}", item);
+                return Some(item.to_string());
+            }
+        }
+
+        None
+    }
+
+    /// Corrects a misspelled track (like "Silverston"), if only one track is close enough
+    pub fn correct_track(&self, track_str: &str) -> Option<String> {
+        let tracks = self.tracks.iter().map(String::as_str);
+        fuzzy::unique_match(&aliases::track_key(track_str), tracks).map(str::to_string)
+    }
+
+    /// Up to 3 tracks that are close to the text, closest first
+    pub fn suggest_tracks(&self, track_str: &str) -> Vec<String> {
+        let track_str = aliases::track_key(track_str);
+        let tracks = self.tracks.iter().map(String::as_str);
+        fuzzy::closest(&track_str, tracks, track_str.len() / 2)
+            .into_iter()
+            .take(3)
+            .map(|(track, _)| track.to_string())
+            .collect()
+    }
+
+    pub fn validate_car_model(&self, model_str: Option<&str>) -> Option<u32> {
+        let id = self.find_car_models(model_str?).first().copied()?;
+        info!(
+            "Found car {} ({})",
+            self.get_car_name_from_id(id).unwrap_or_default(),
+            id
+        );
+        Some(id)
+    }
+
+    /// Finds all cars the text could mean, in the order of the catalog.
+    /// An alias, an id, an id in brackets (like "Audi R8 [19]") or the exact name only match that car,
+    /// otherwise every car whose name contains all words of the text is a candidate.
+    pub fn find_car_models(&self, text: &str) -> Vec<u32> {
+        let text = text.trim();
+        if let Some(id) = self.aliases.car(text) {
+            return vec![id];
+        }
+
+        // Finding based on ID
+        let id_text = text
+            .strip_suffix(']')
+            .and_then(|rest| rest.rsplit_once('['))
+            .map(|(_, id)| id.trim())
+            .unwrap_or(text);
+        if let Ok(id) = u32::from_str(id_text) {
+            if self.get_car_name_from_id(id).is_some() {
+                return vec![id];
+            }
+            if id_text != text {
+                // The brackets were meant to pick a car, so we don't guess
+                return vec![];
+            }
+        }
+
+        if let Some(car) = self
+            .cars
+            .iter()
+            .find(|car| car.name.eq_ignore_ascii_case(text))
+        {
+            return vec![car.id];
+        }
+
+        // We try to find the car based on the name, specifically we turn the text into tokens and then see if one carname contains all tokens
+        let keywords: Vec<String> = text
+            .split([' ', ','])
+            .filter(|sample| !sample.trim().is_empty())
+            .map(|key| key.trim().to_lowercase())
+            .collect();
+        if keywords.is_empty() {
+            return vec![];
+        }
+
+        self.cars
+            .iter()
+            .filter(|car| {
+                let car_name_compare = car.name.to_lowercase();
+                keywords
+                    .iter()
+                    .all(|key| car_name_compare.contains(key.as_str()))
+            })
+            .map(|car| car.id)
+            .collect()
+    }
+
+    // Every word of the car names, lowercase
+    fn car_name_words(&self) -> Vec<String> {
+        let mut words: Vec<String> = self
+            .cars
+            .iter()
+            .flat_map(|car| car.name.split(' '))
+            .map(|word| word.to_lowercase())
+            .collect();
+        words.sort();
+        words.dedup();
+        words
+    }
+
+    /// Corrects misspelled words of a car label (like "Lamborgini"), if each is close to only one word of the car names.
+    /// None if there was nothing to correct or a word could not be corrected.
+    pub fn correct_car_model(&self, text: &str) -> Option<String> {
+        let names: Vec<String> = self
+            .cars
+            .iter()
+            .map(|car| car.name.to_lowercase())
+            .collect();
+        let words = self.car_name_words();
+
+        let mut corrected = false;
+        let mut keywords = vec![];
+        for key in text.split([' ', ',']).filter(|key| !key.trim().is_empty()) {
+            let key = key.trim().to_lowercase();
+            if names.iter().any(|name| name.contains(key.as_str())) {
+                keywords.push(key);
+            } else {
+                let word = fuzzy::unique_match(&key, words.iter().map(String::as_str))?;
+                keywords.push(word.to_string());
+                corrected = true;
+            }
+        }
+
+        corrected.then(|| keywords.join(" "))
+    }
+
+    /// Up to 3 cars that are close to the text, closest first
+    pub fn suggest_car_models(&self, text: &str) -> Vec<u32> {
+        let keywords: Vec<String> = text
+            .split([' ', ','])
+            .filter(|key| !key.trim().is_empty())
+            .map(|key| key.trim().to_lowercase())
+            .collect();
+        let max_distance = keywords.iter().map(|key| key.len()).sum::<usize>() / 2;
+
+        // Each word counts with its distance to the closest word of the car name
+        let mut scores: Vec<(u32, usize)> = self
+            .cars
+            .iter()
+            .map(|car| {
+                let name = car.name.to_lowercase();
+                let score = keywords
+                    .iter()
+                    .map(|key| {
+                        if name.contains(key.as_str()) {
+                            0
+                        } else {
+                            name.split(' ')
+                                .map(|word| fuzzy::distance(key, word))
+                                .min()
+                                .unwrap_or(key.len())
+                        }
+                    })
+                    .sum::<usize>();
+                (car.id, score)
+            })
+            .filter(|(_, score)| *score <= max_distance)
+            .collect();
+        scores.sort_by_key(|(_, score)| *score);
+        scores.into_iter().take(3).map(|(id, _)| id).collect()
+    }
+
+    /// The class of the car, None if the id is not known
+    pub fn get_car_class(&self, car_id: u32) -> Option<CarClass> {
+        self.cars
+            .iter()
+            .find(|car| car.id == car_id)
+            .map(|car| car.class)
+    }
+
+    /// All cars of the class, in the order of the catalog
+    pub fn cars_of_class(&self, class: CarClass) -> impl Iterator<Item = &Car> {
+        self.cars.iter().filter(move |car| car.class == class)
+    }
+
+    pub fn get_car_name_from_id(&self, car_id: u32) -> Option<String> {
+        // Couldn't we put all ids and names into a map? Yes, but considering that we have only about 50, this is not a performance issue
+        self.cars
+            .iter()
+            .find(|car| car.id == car_id)
+            .map(|car| car.name.clone())
+    }
+}
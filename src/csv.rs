@@ -0,0 +1,122 @@
+// Minimal RFC 4180 reader/writer, as spreadsheet programs quote cells containing the delimiter, quotes or line breaks
+
+use crate::{Error, Result};
+
+// Delimiters we look for when none is given, European Excel locales use ';'
+pub const DELIMITERS: [char; 3] = [',', ';', '\t'];
+
+/// Guesses the delimiter by counting the candidates (outside of quotes) in the header row.
+/// Defaults to ',' if none of them appear.
+pub fn detect_delimiter(text: &str) -> char {
+    let mut counts = [0usize; DELIMITERS.len()];
+    let mut in_quotes = false;
+
+    for c in text.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '\r' | '\n' if !in_quotes => break,
+            c if !in_quotes => {
+                if let Some(index) = DELIMITERS.iter().position(|d| *d == c) {
+                    counts[index] += 1;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let mut best = 0;
+    for index in 1..DELIMITERS.len() {
+        if counts[index] > counts[best] {
+            best = index;
+        }
+    }
+    DELIMITERS[best]
+}
+
+/// Splits the text into records of cells.
+/// Supports quoted cells (with "" as an escaped quote), CRLF/LF/CR line endings and ignores a trailing line break.
+pub fn read_records(text: &str, delimiter: char) -> Result<Vec<Vec<String>>> {
+    let mut records = Vec::<Vec<String>>::new();
+    let mut record = Vec::<String>::new();
+    let mut field = String::new();
+
+    let mut in_quotes = false;
+    let mut field_started = false; // only a quote at the start of a cell opens a quoted cell
+    let mut quote_row = 0;
+
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    in_quotes = false;
+                }
+            } else {
+                field.push(c);
+            }
+            continue;
+        }
+
+        match c {
+            '"' if !field_started => {
+                in_quotes = true;
+                field_started = true;
+                quote_row = records.len() + 1;
+            }
+            '\r' | '\n' => {
+                if c == '\r' && chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                field_started = false;
+            }
+            c if c == delimiter => {
+                record.push(std::mem::take(&mut field));
+                field_started = false;
+            }
+            c => {
+                field.push(c);
+                field_started = true;
+            }
+        }
+    }
+
+    if in_quotes {
+        return Err(Error::UnterminatedQuote { row: quote_row });
+    }
+
+    // Last line without line break
+    if field_started || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+/// Writes the records, quoting cells where required
+pub fn write_records(records: &[Vec<String>], delimiter: char) -> String {
+    let mut output = String::new();
+    for record in records {
+        for (index, cell) in record.iter().enumerate() {
+            if index != 0 {
+                output.push(delimiter);
+            }
+
+            if cell.contains(delimiter) || cell.contains(['"', '\n', '\r']) {
+                output.push('"');
+                output.push_str(cell.replace('"', "\"\"").as_str());
+                output.push('"');
+            } else {
+                output.push_str(cell.as_str());
+            }
+        }
+        output.push('\n');
+    }
+
+    output
+}
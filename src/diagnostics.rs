@@ -0,0 +1,248 @@
+// Problems found while reading the sheets, collected so they can be reported at the end instead of getting lost in the log
+
+use std::fmt;
+
+use log::trace;
+use serde::Serialize;
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    // The value was changed, but still made it into the bop
+    Warning,
+    // Something was left out of the bop
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    UnknownTrack,
+    UnknownCar,
+    // The car label matches more than one car
+    AmbiguousCar,
+    // A track or car that is not known, but close to one
+    Misspelled,
+    UnparsableCell,
+    OutOfRange,
+    // The same track and car appear twice in a sheet
+    Duplicate,
+    // Merged files set different values for the same track and car
+    Conflict,
+}
+
+// Serialized as {"type": "clamped", "value": 40}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum Action {
+    SkippedColumn,
+    SkippedRow,
+    SkippedCell,
+    Clamped(i32),
+    Ignored,
+    // The car with this id was used
+    Matched(u32),
+    // The closest known name was used
+    Corrected,
+    // The value replaced an earlier one
+    Replaced,
+    // The value was added to an earlier one
+    Summed,
+    // Parsing stopped here
+    Aborted,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: Kind,
+    pub file: String,
+    // Counted like in a spreadsheet, starting at 1, 0 if the problem is not in a single cell
+    pub row: usize,
+    pub column: usize,
+    // The original text of the cell
+    pub text: String,
+    pub message: String,
+    pub action: Action,
+}
+
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    pub items: Vec<Diagnostic>,
+    // Every diagnostic aborts the parsing with an Error
+    pub strict: bool,
+    file: String,
+}
+
+impl Diagnostics {
+    pub fn new(strict: bool) -> Diagnostics {
+        Diagnostics {
+            strict,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the file the following diagnostics belong to
+    pub fn set_file(&mut self, file: &str) {
+        self.file = file.to_string();
+    }
+
+    /// Records the diagnostic, in strict mode this returns the matching Error
+    pub fn push(
+        &mut self,
+        kind: Kind,
+        (row, column): (usize, usize),
+        text: &str,
+        message: String,
+        action: Action,
+    ) -> Result<()> {
+        // In strict mode every diagnostic stops the conversion, the report has to say so
+        let action = if self.strict { Action::Aborted } else { action };
+        let severity = match (kind, action) {
+            (_, Action::Aborted) => Severity::Error,
+            (
+                Kind::OutOfRange
+                | Kind::Duplicate
+                | Kind::AmbiguousCar
+                | Kind::Misspelled
+                | Kind::Conflict,
+                _,
+            ) => Severity::Warning,
+            _ => Severity::Error,
+        };
+        let diagnostic = Diagnostic {
+            severity,
+            kind,
+            file: self.file.clone(),
+            row,
+            column,
+            text: text.to_string(),
+            message,
+            action,
+        };
+        trace!("{}", diagnostic);
+        self.items.push(diagnostic);
+
+        if !self.strict {
+            return Ok(());
+        }
+
+        let text = text.to_string();
+        Err(match kind {
+            Kind::UnknownTrack => Error::UnknownTrack {
+                row,
+                column,
+                name: text,
+            },
+            Kind::UnknownCar => Error::UnknownCar {
+                row,
+                column,
+                name: text,
+            },
+            Kind::AmbiguousCar => Error::AmbiguousCar {
+                row,
+                column,
+                name: text,
+            },
+            Kind::Misspelled => Error::Misspelled {
+                row,
+                column,
+                name: text,
+            },
+            Kind::UnparsableCell => Error::UnparsableCell { row, column, text },
+            Kind::OutOfRange => Error::OutOfRange { row, column, text },
+            Kind::Duplicate => Error::Duplicate { row, column, text },
+            Kind::Conflict => Error::Conflict {
+                file: self.file.clone(),
+                text,
+            },
+        })
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.severity == severity)
+            .count()
+    }
+
+    /// The report as json, for tools that want to show the problems themselves
+    pub fn to_json(&self) -> Result<String> {
+        #[derive(Serialize)]
+        struct Report<'a> {
+            errors: usize,
+            warnings: usize,
+            diagnostics: &'a [Diagnostic],
+        }
+
+        Ok(serde_json::to_string_pretty(&Report {
+            errors: self.count(Severity::Error),
+            warnings: self.count(Severity::Warning),
+            diagnostics: &self.items,
+        })?)
+    }
+
+    /// The highest severity of all diagnostics, None if there are none
+    pub fn severity(&self) -> Option<Severity> {
+        self.items.iter().map(|item| item.severity).max()
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::SkippedColumn => f.write_str("skipped column"),
+            Action::SkippedRow => f.write_str("skipped row"),
+            Action::SkippedCell => f.write_str("skipped cell"),
+            Action::Clamped(value) => write!(f, "clamped to {}", value),
+            Action::Ignored => f.write_str("ignored"),
+            Action::Matched(id) => write!(f, "using car model {}", id),
+            Action::Corrected => f.write_str("corrected"),
+            Action::Replaced => f.write_str("replaced the earlier value"),
+            Action::Summed => f.write_str("added to the earlier value"),
+            Action::Aborted => f.write_str("aborted"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.row == 0 {
+            write!(f, "{}", self.file)?;
+        } else {
+            write!(f, "{}:{}:{}", self.file, self.row, self.column)?;
+        }
+        write!(
+            f,
+            ": {}: {} ('{}'), {}",
+            self.severity, self.message, self.text, self.action
+        )
+    }
+}
+
+// The report printed at the end
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Found {} error(s) and {} warning(s):",
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        )?;
+        for item in self.items.iter() {
+            writeln!(f, "{}", item)?;
+        }
+        Ok(())
+    }
+}
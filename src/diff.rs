@@ -0,0 +1,192 @@
+// What changed between two bop.json files, like two published versions of a BOP
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use serde::Serialize;
+
+use crate::{data::BOP, format_values, Catalog, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Values {
+    pub ballast_kg: Option<i32>,
+    pub restrictor: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub track: String,
+    pub car_model: u32,
+    // The name of the car, the id if the catalog doesn't know it
+    pub car: String,
+    pub change: ChangeKind,
+    // None if the entry was added
+    pub old: Option<Values>,
+    // None if the entry was removed
+    pub new: Option<Values>,
+}
+
+/// The added, removed and changed entries, sorted by track and car name.
+/// Entries without any values count as missing, as they don't change the BOP.
+pub fn diff_bops(old: &BOP, new: &BOP, catalog: &Catalog) -> Vec<Change> {
+    let values = |bop: &BOP| -> BTreeMap<(String, u32), Values> {
+        bop.entries
+            .iter()
+            .filter(|entry| entry.ballast_kg.is_some() || entry.restrictor.is_some())
+            .map(|entry| {
+                (
+                    (entry.track.clone(), entry.car_model),
+                    Values {
+                        ballast_kg: entry.ballast_kg,
+                        restrictor: entry.restrictor,
+                    },
+                )
+            })
+            .collect()
+    };
+    let old = values(old);
+    let mut new = values(new);
+
+    let mut changes = vec![];
+    for ((track, car_model), old) in old {
+        let new = new.remove(&(track.clone(), car_model));
+        let change = match new {
+            None => ChangeKind::Removed,
+            Some(new) if new != old => ChangeKind::Changed,
+            Some(_) => continue,
+        };
+        changes.push((track, car_model, change, Some(old), new));
+    }
+    for ((track, car_model), new) in new {
+        changes.push((track, car_model, ChangeKind::Added, None, Some(new)));
+    }
+
+    let mut changes: Vec<Change> = changes
+        .into_iter()
+        .map(|(track, car_model, change, old, new)| Change {
+            track,
+            car_model,
+            car: catalog
+                .get_car_name_from_id(car_model)
+                .unwrap_or(car_model.to_string()),
+            change,
+            old,
+            new,
+        })
+        .collect();
+    changes.sort_by(|a, b| (&a.track, &a.car).cmp(&(&b.track, &b.car)));
+    changes
+}
+
+fn describe(values: Option<Values>) -> String {
+    values
+        .and_then(|values| format_values(values.ballast_kg, values.restrictor))
+        .unwrap_or("-".to_string())
+}
+
+/// One block per track, with a line per car like "~ BMW M4 GT3 (30): 10kg -> 15kg"
+pub fn format_text(changes: &[Change]) -> String {
+    if changes.is_empty() {
+        return "No changes\n".to_string();
+    }
+
+    let mut text = String::new();
+    let mut track = None;
+    for change in changes {
+        if track != Some(&change.track) {
+            track = Some(&change.track);
+            let _ = writeln!(text, "{}", change.track);
+        }
+        let _ = match change.change {
+            ChangeKind::Added => writeln!(
+                text,
+                "  + {} ({}): {}",
+                change.car,
+                change.car_model,
+                describe(change.new)
+            ),
+            ChangeKind::Removed => writeln!(
+                text,
+                "  - {} ({}): {}",
+                change.car,
+                change.car_model,
+                describe(change.old)
+            ),
+            ChangeKind::Changed => writeln!(
+                text,
+                "  ~ {} ({}): {} -> {}",
+                change.car,
+                change.car_model,
+                describe(change.old),
+                describe(change.new)
+            ),
+        };
+    }
+    text
+}
+
+/// One table per track, for pasting into release notes
+pub fn format_markdown(changes: &[Change]) -> String {
+    if changes.is_empty() {
+        return "No changes\n".to_string();
+    }
+
+    let mut text = String::new();
+    let mut track = None;
+    for change in changes {
+        if track != Some(&change.track) {
+            if track.is_some() {
+                text.push('\n');
+            }
+            track = Some(&change.track);
+            let _ = writeln!(text, "## {}\n", change.track);
+            let _ = writeln!(text, "| Car | Change | Old | New |");
+            let _ = writeln!(text, "| --- | --- | --- | --- |");
+        }
+        let _ = writeln!(
+            text,
+            "| {} ({}) | {} | {} | {} |",
+            change.car,
+            change.car_model,
+            match change.change {
+                ChangeKind::Added => "added",
+                ChangeKind::Removed => "removed",
+                ChangeKind::Changed => "changed",
+            },
+            describe(change.old),
+            describe(change.new)
+        );
+    }
+    text
+}
+
+/// The changes with their counts, for tools that want to show them themselves
+pub fn format_json(changes: &[Change]) -> Result<String> {
+    #[derive(Serialize)]
+    struct Report<'a> {
+        added: usize,
+        removed: usize,
+        changed: usize,
+        changes: &'a [Change],
+    }
+
+    let count = |kind: ChangeKind| {
+        changes
+            .iter()
+            .filter(|change| change.change == kind)
+            .count()
+    };
+    Ok(serde_json::to_string_pretty(&Report {
+        added: count(ChangeKind::Added),
+        removed: count(ChangeKind::Removed),
+        changed: count(ChangeKind::Changed),
+        changes,
+    })?)
+}
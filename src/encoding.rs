@@ -0,0 +1,112 @@
+// Text encoding detection, spreadsheet programs and the ACC server don't agree on UTF-8
+
+use std::{fmt, path::Path};
+
+use encoding_rs::{UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use log::trace;
+
+use crate::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Windows1252,
+}
+
+impl fmt::Display for TextEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TextEncoding::Utf8 => "UTF-8",
+            TextEncoding::Utf16Le => "UTF-16 LE",
+            TextEncoding::Utf16Be => "UTF-16 BE",
+            TextEncoding::Windows1252 => "Windows-1252",
+        })
+    }
+}
+
+/// Figures out the encoding of the raw bytes.
+/// A BOM always wins, otherwise we check for UTF-16 (via the zero bytes of ASCII characters), then UTF-8,
+/// and fall back to Windows-1252 (which can decode anything).
+pub fn detect(raw: &[u8]) -> TextEncoding {
+    if raw.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        TextEncoding::Utf8
+    } else if raw.starts_with(&[0xFF, 0xFE]) {
+        TextEncoding::Utf16Le
+    } else if raw.starts_with(&[0xFE, 0xFF]) {
+        TextEncoding::Utf16Be
+    } else if let Some(encoding) = guess_utf16(raw) {
+        encoding
+    } else if std::str::from_utf8(raw).is_ok() {
+        TextEncoding::Utf8
+    } else {
+        TextEncoding::Windows1252
+    }
+}
+
+// Our files are mostly ASCII, so UTF-16 without BOM has a zero byte in every other position
+fn guess_utf16(raw: &[u8]) -> Option<TextEncoding> {
+    let sample = &raw[..raw.len().min(1024)];
+    let pairs = sample.chunks_exact(2);
+    let total = pairs.len();
+    if total == 0 {
+        return None;
+    }
+
+    let even = pairs.clone().filter(|pair| pair[0] == 0).count();
+    let odd = pairs.filter(|pair| pair[1] == 0).count();
+
+    if odd * 2 > total && even == 0 {
+        Some(TextEncoding::Utf16Le)
+    } else if even * 2 > total && odd == 0 {
+        Some(TextEncoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+/// Decodes the raw bytes into a string (without BOM), detecting the encoding
+pub fn decode(raw: &[u8], path: &Path) -> Result<String> {
+    let encoding = detect(raw);
+    trace!("Reading {} as {}", path.display(), encoding);
+
+    match encoding {
+        TextEncoding::Utf8 => {
+            let raw = raw.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(raw);
+            UTF_8
+                .decode_without_bom_handling_and_without_replacement(raw)
+                .map(|text| text.into_owned())
+                .ok_or_else(|| Error::InvalidUtf8(path.to_path_buf()))
+        }
+        TextEncoding::Utf16Le | TextEncoding::Utf16Be => {
+            let (codec, raw) = if encoding == TextEncoding::Utf16Le {
+                (UTF_16LE, raw.strip_prefix(&[0xFF, 0xFE]).unwrap_or(raw))
+            } else {
+                (UTF_16BE, raw.strip_prefix(&[0xFE, 0xFF]).unwrap_or(raw))
+            };
+            codec
+                .decode_without_bom_handling_and_without_replacement(raw)
+                .map(|text| text.into_owned())
+                .ok_or_else(|| Error::InvalidUtf16(path.to_path_buf()))
+        }
+        TextEncoding::Windows1252 => {
+            let (text, _) = WINDOWS_1252.decode_without_bom_handling(raw);
+            Ok(text.into_owned())
+        }
+    }
+}
+
+/// Encodes the text for writing, UTF-16 is written without BOM just like the ACC server does.
+/// Characters Windows-1252 can't represent are replaced.
+pub fn encode(text: &str, encoding: TextEncoding) -> Vec<u8> {
+    match encoding {
+        TextEncoding::Utf8 => text.as_bytes().to_vec(),
+        TextEncoding::Utf16Le => text.encode_utf16().flat_map(|c| c.to_le_bytes()).collect(),
+        TextEncoding::Utf16Be => text.encode_utf16().flat_map(|c| c.to_be_bytes()).collect(),
+        TextEncoding::Windows1252 => {
+            let (raw, _, _) = WINDOWS_1252.encode(text);
+            raw.into_owned()
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+// Rows and columns are counted like in a spreadsheet, starting at 1 with the header row/label column
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("File {0} does not exist")]
+    FileMissing(PathBuf),
+
+    #[error("File {0} is not valid UTF-8")]
+    InvalidUtf8(PathBuf),
+
+    #[error("File {0} is not valid UTF-16")]
+    InvalidUtf16(PathBuf),
+
+    #[error("Unable to parse track '{name}' in row {row}, column {column}")]
+    UnknownTrack {
+        row: usize,
+        column: usize,
+        name: String,
+    },
+
+    #[error("Unable to parse car model '{name}' in row {row}, column {column}")]
+    UnknownCar {
+        row: usize,
+        column: usize,
+        name: String,
+    },
+
+    #[error("Car model '{name}' in row {row}, column {column} matches more than one car")]
+    AmbiguousCar {
+        row: usize,
+        column: usize,
+        name: String,
+    },
+
+    #[error("'{name}' in row {row}, column {column} is not known, but close to a known name")]
+    Misspelled {
+        row: usize,
+        column: usize,
+        name: String,
+    },
+
+    #[error("Unable to parse '{text}' in row {row}, column {column}")]
+    UnparsableCell {
+        row: usize,
+        column: usize,
+        text: String,
+    },
+
+    #[error("Value '{text}' in row {row}, column {column} is out of range")]
+    OutOfRange {
+        row: usize,
+        column: usize,
+        text: String,
+    },
+
+    #[error("'{text}' in row {row}, column {column} sets a car and track that were already set")]
+    Duplicate {
+        row: usize,
+        column: usize,
+        text: String,
+    },
+
+    #[error("'{text}' in {file} sets a car and track an earlier file set to a different value")]
+    Conflict { file: String, text: String },
+
+    #[error("Quoted cell starting in row {row} is never closed")]
+    UnterminatedQuote { row: usize },
+
+    #[error("File {0} already exists and may not be overwritten")]
+    OutputExists(PathBuf),
+
+    #[error("Output path {0} is a folder, please point at a File")]
+    OutputIsFolder(PathBuf),
+
+    #[error("Workbook {path} does not contain a sheet named '{sheet}'")]
+    SheetMissing { path: PathBuf, sheet: String },
+
+    #[error("Alias '{alias}' points at '{target}', which is not a known track or car")]
+    InvalidAlias { alias: String, target: String },
+
+    #[error("Catalog {path} is invalid: {reason}")]
+    InvalidCatalog { path: PathBuf, reason: String },
+
+    #[error("Patch is invalid: {reason}")]
+    InvalidPatch { reason: String },
+
+    #[error("Toml error: {0}")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("Spreadsheet error: {0}")]
+    Spreadsheet(#[from] calamine::Error),
+
+    #[error("Xlsx error: {0}")]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+
+    #[error("Zip error: {0}")]
+    Zip(#[from] zip::result::ZipError),
+
+    #[error("Json error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,52 @@
+// Typo tolerant matching of names, based on the edit distance
+
+/// Levenshtein distance, the number of inserted, removed or replaced characters to turn a into b
+pub fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = replace.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+/// How many typos we accept in a word of this length, short words have to be exact
+pub fn tolerance(len: usize) -> usize {
+    (len / 4).min(2)
+}
+
+/// The candidates within max_distance of the text, closest first (ties keep the order of the candidates)
+pub fn closest<'a>(
+    text: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+    max_distance: usize,
+) -> Vec<(&'a str, usize)> {
+    let mut found: Vec<(&str, usize)> = candidates
+        .into_iter()
+        .map(|candidate| (candidate, distance(text, candidate)))
+        .filter(|(_, distance)| *distance <= max_distance)
+        .collect();
+    found.sort_by_key(|(_, distance)| *distance);
+    found
+}
+
+/// The one candidate within the tolerance for the text, None if there is none or a tie
+pub fn unique_match<'a>(
+    text: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let found = closest(text, candidates, tolerance(text.chars().count()));
+    match found.as_slice() {
+        [(best, _)] => Some(best),
+        [(best, first), (_, second), ..] if first < second => Some(best),
+        _ => None,
+    }
+}
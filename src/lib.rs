@@ -0,0 +1,1444 @@
+use std::{collections::HashMap, fmt, fs, iter::zip, path::PathBuf, str::FromStr};
+
+use log::{info, trace};
+use serde::de::DeserializeOwned;
+
+pub mod aliases;
+pub mod catalog;
+pub mod csv;
+pub mod data;
+pub mod diagnostics;
+pub mod diff;
+pub mod encoding;
+pub mod error;
+pub mod fuzzy;
+pub mod merge;
+pub mod output;
+pub mod patch;
+pub mod workbook;
+pub use aliases::Aliases;
+pub use catalog::{CarClass, Catalog};
+use data::{Entry, BOP};
+pub use diagnostics::Diagnostics;
+use diagnostics::{Action, Kind};
+pub use error::{Error, Result};
+pub use output::{check_overwrite, write_bop, OverwritePolicy};
+
+#[cfg(test)]
+mod test;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BopType {
+    Ballast,
+    Restrictor,
+    // Cells contain both, like 15kg/3%
+    Combined,
+}
+
+impl fmt::Display for BopType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BopType::Ballast => "Ballast",
+            BopType::Restrictor => "Restrictor",
+            BopType::Combined => "Combined",
+        })
+    }
+}
+
+fn read_file(path: &PathBuf) -> Result<String> {
+    if !path.is_file() {
+        return Err(Error::FileMissing(path.clone()));
+    }
+
+    let raw = fs::read(path)?;
+    encoding::decode(&raw, path)
+}
+
+// Settings files (catalog, aliases, patches) are toml if the extension says so, otherwise json
+pub(crate) fn read_toml_or_json<T: DeserializeOwned>(path: &PathBuf) -> Result<T> {
+    let text = read_file(path)?;
+    let is_toml = path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("toml"))
+        .unwrap_or(false);
+    if is_toml {
+        Ok(toml::from_str(&text)?)
+    } else {
+        Ok(serde_json::from_str(&text)?)
+    }
+}
+
+// Settings for reading and writing csv files
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CsvOptions {
+    // None detects the delimiter when reading and uses ',' when writing
+    pub delimiter: Option<char>,
+    // Write ballast and restrictor into the same cells (15kg/3%) of a single table
+    pub combined: bool,
+    // None detects the format from the header row when reading and uses the matrix when writing
+    pub format: Option<TableFormat>,
+    // What to do when a sheet sets the same car at the same track twice
+    pub duplicates: DuplicatePolicy,
+    // Only cars of this class are read and written, None for all cars
+    pub class: Option<CarClass>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    // Abort the parsing
+    Error,
+    FirstWins,
+    #[default]
+    LastWins,
+    // Add up the values, the result is clamped again
+    Sum,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableFormat {
+    // Tracks in the top row, cars in the first column
+    Matrix,
+    // One row per entry with the columns track,car,ballast_kg,restrictor
+    Long,
+    // Cars in the top row, tracks in the first column
+    Transposed,
+}
+
+pub const LONG_HEADER: [&str; 4] = ["track", "car", "ballast_kg", "restrictor"];
+
+// The limits of the ACC server, ballast can be negative
+pub const MAX_BALLAST_KG: i32 = 40;
+pub const MAX_RESTRICTOR: i32 = 20;
+
+impl TableFormat {
+    /// The long format is recognized by its track and car column headers.
+    /// A matrix is transposed if more tracks are found in the first column than in the top row.
+    pub fn detect(table: &[Vec<String>], catalog: &Catalog) -> TableFormat {
+        let header = table.first().map(|row| row.as_slice()).unwrap_or_default();
+        let has_column = |names: &[&str]| {
+            header.iter().any(|cell| {
+                names
+                    .iter()
+                    .any(|name| cell.trim().eq_ignore_ascii_case(name))
+            })
+        };
+
+        if has_column(&["track"]) && has_column(&["car", "car_model", "carmodel"]) {
+            return TableFormat::Long;
+        }
+
+        let top_row = header
+            .iter()
+            .skip(1)
+            .filter(|cell| catalog.validate_track(cell).is_some())
+            .count();
+        let first_column = table
+            .iter()
+            .skip(1)
+            .filter_map(|row| row.first())
+            .filter(|cell| catalog.validate_track(cell).is_some())
+            .count();
+
+        if first_column > top_row {
+            TableFormat::Transposed
+        } else {
+            TableFormat::Matrix
+        }
+    }
+}
+
+/// Parses a ballast or restrictor csv into entries.
+/// Cells, tracks and cars that can not be parsed are skipped and recorded in the diagnostics.
+pub fn parse_csv(
+    csv_file_path: String,
+    file_type: BopType,
+    options: CsvOptions,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Entry>> {
+    let path = PathBuf::from(&csv_file_path);
+
+    info!("Loading {} file {}", file_type, &csv_file_path);
+    diagnostics.set_file(&csv_file_path);
+
+    let file = read_file(&path)?;
+
+    trace!("Parsing data...");
+
+    let delimiter = options.delimiter.unwrap_or_else(|| {
+        let delimiter = csv::detect_delimiter(&file);
+        trace!("Detected delimiter '{}'", delimiter.escape_default());
+        delimiter
+    });
+    let table = csv::read_records(&file, delimiter)?;
+    read_table(&table, file_type, options, catalog, diagnostics)
+}
+
+/// Parses a csv or workbook (.ods/.xlsx), based on the file extension.
+/// The sheet is only used for workbooks.
+/// Cells set to 0 are kept as Some(0), so they can clear the values of earlier layers.
+pub fn parse_file(
+    file_path: String,
+    file_type: BopType,
+    options: CsvOptions,
+    sheet: Option<&str>,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Entry>> {
+    let path = PathBuf::from(&file_path);
+    if workbook::is_workbook(&path) {
+        info!("Loading {} workbook {}", file_type, &file_path);
+        diagnostics.set_file(&file_path);
+        let table = workbook::read_sheet(&path, sheet, file_type)?;
+        return read_table(&table, file_type, options, catalog, diagnostics);
+    }
+
+    parse_csv(file_path, file_type, options, catalog, diagnostics)
+}
+
+fn read_table(
+    table: &[Vec<String>],
+    file_type: BopType,
+    options: CsvOptions,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Entry>> {
+    let format = options
+        .format
+        .unwrap_or_else(|| TableFormat::detect(table, catalog));
+    trace!("Reading table as {:?}", format);
+
+    match format {
+        TableFormat::Matrix => parse_matrix(table, file_type, options, false, catalog, diagnostics),
+        TableFormat::Transposed => {
+            parse_matrix(table, file_type, options, true, catalog, diagnostics)
+        }
+        TableFormat::Long => parse_long_table(table, options, catalog, diagnostics),
+    }
+}
+
+/// Interprets a table in the long format, one row per entry with a track, car, ballast_kg and restrictor column.
+/// The columns are found by their header, so their order does not matter and ballast_kg or restrictor can be left out.
+pub fn parse_long_table(
+    table: &[Vec<String>],
+    options: CsvOptions,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Entry>> {
+    let mut rows = table.iter();
+    let header: Vec<String> = rows
+        .next()
+        .map(|row| row.iter().map(|cell| cell.trim().to_lowercase()).collect())
+        .unwrap_or_default();
+    let column = |names: &[&str]| {
+        header
+            .iter()
+            .position(|cell| names.contains(&cell.as_str()))
+    };
+
+    let track_col = column(&["track"]);
+    let car_col = column(&["car", "car_model", "carmodel"]);
+    let ballast_col = column(&["ballast_kg", "ballastkg", "ballast"]);
+    let restrictor_col = column(&["restrictor"]);
+
+    let mut entries = EntryList::new(options.duplicates);
+    for (index, row) in rows.enumerate() {
+        let row_number = index + 2; // +1 for the header, +1 as spreadsheets count from 1
+        if row.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+        let cell = |col: Option<usize>| {
+            col.and_then(|col| row.get(col))
+                .map(|cell| cell.trim())
+                .unwrap_or_default()
+        };
+        let position = |col: Option<usize>| (row_number, col.unwrap_or_default() + 1);
+
+        let column = resolve_track_label(
+            cell(track_col),
+            position(track_col),
+            Action::SkippedRow,
+            catalog,
+            diagnostics,
+        )?;
+        let Some(column) = column else {
+            continue;
+        };
+
+        let label = resolve_car_label(
+            cell(car_col),
+            position(car_col),
+            Action::SkippedRow,
+            options.class,
+            catalog,
+            diagnostics,
+        )?;
+        let Some(label) = label else {
+            continue;
+        };
+
+        // We reuse the cell parsing of the matrix, so units work here too
+        // Duplicates are reported at the car cell
+        let car = CellRef {
+            text: cell(car_col),
+            position: position(car_col),
+            model: label.models[0],
+            car: &label.name,
+            track: &column.name,
+            from_class: label.from_class,
+            from_default: column.from_default,
+        };
+        let ballast = CellRef {
+            text: cell(ballast_col),
+            position: position(ballast_col),
+            ..car
+        };
+        let restrictor = CellRef {
+            text: cell(restrictor_col),
+            position: position(restrictor_col),
+            ..car
+        };
+        if ballast.text.is_empty() && restrictor.text.is_empty() {
+            // Rows without values don't set anything
+            continue;
+        }
+        // A cell that can't be parsed is skipped, the value of the other one is still used
+        let ballast = create_entry(&ballast, BopType::Ballast, diagnostics)?;
+        let restrictor = create_entry(&restrictor, BopType::Restrictor, diagnostics)?;
+        if ballast.is_none() && restrictor.is_none() {
+            continue;
+        }
+        let value =
+            |first: &Option<Entry>, second: &Option<Entry>, get: fn(&Entry) -> Option<i32>| {
+                first
+                    .as_ref()
+                    .and_then(get)
+                    .or(second.as_ref().and_then(get))
+            };
+        let ballast_kg = value(&ballast, &restrictor, |entry| entry.ballast_kg);
+        let restrictor = value(&restrictor, &ballast, |entry| entry.restrictor);
+        for (track, model) in column
+            .tracks
+            .iter()
+            .flat_map(|track| label.models.iter().map(move |model| (track, model)))
+        {
+            entries.add(
+                Entry {
+                    track: track.clone(),
+                    car_model: *model,
+                    ballast_kg,
+                    restrictor,
+                },
+                &car,
+                diagnostics,
+            )?;
+        }
+    }
+    info!("Parsed {} entries", entries.entries.len());
+
+    Ok(entries.entries)
+}
+
+/// Interprets a table with the tracks in the top row and the cars in the first column.
+/// This is independent of the file format the table was read from.
+pub fn parse_table(
+    table: &[Vec<String>],
+    file_type: BopType,
+    options: CsvOptions,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Entry>> {
+    parse_matrix(table, file_type, options, false, catalog, diagnostics)
+}
+
+// Transposed tables have the tracks in the first column and the cars in the top row.
+// We flip them so the parsing is the same, but report the positions of the original table.
+fn parse_matrix(
+    table: &[Vec<String>],
+    file_type: BopType,
+    options: CsvOptions,
+    transposed: bool,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Entry>> {
+    let flipped;
+    let table = if transposed {
+        flipped = transpose(table);
+        flipped.as_slice()
+    } else {
+        table
+    };
+    // row and column in the table as the user sees it
+    let position = |row: usize, column: usize| {
+        if transposed {
+            (column, row)
+        } else {
+            (row, column)
+        }
+    };
+
+    let mut file = table.iter();
+    let mut toprow = file.next().map(|row| row.iter()).unwrap_or_default();
+    toprow.next();
+
+    let mut tracks: Vec<Option<TrackLabel>> = vec![];
+    for (index, element) in toprow.enumerate() {
+        let element = element.trim();
+        if element.is_empty() {
+            // trailing empty header cells are common in spreadsheet exports, so we only report named ones
+            tracks.push(None);
+            continue;
+        }
+
+        // This has to be an option, as we need to later be able to keep the columns intact for the weights
+        tracks.push(resolve_track_label(
+            element,
+            position(1, index + 2),
+            if transposed {
+                Action::SkippedRow
+            } else {
+                Action::SkippedColumn
+            },
+            catalog,
+            diagnostics,
+        )?);
+    }
+    info!(
+        "Found {} tracks",
+        tracks
+            .iter()
+            .flatten()
+            .filter(|column| !column.from_default)
+            .count()
+    );
+
+    let mut entries = EntryList::new(options.duplicates);
+    let mut count = 0;
+    for (index, car) in file.enumerate() {
+        let row_number = index + 2; // +1 for the header, +1 as spreadsheets count from 1
+        if car.iter().all(|cell| cell.trim().is_empty()) {
+            continue;
+        }
+
+        let mut row = car.iter().map(|cell| cell.trim());
+        let label = row.next();
+        let label = resolve_car_label(
+            label.unwrap_or_default(),
+            position(row_number, 1),
+            if transposed {
+                Action::SkippedColumn
+            } else {
+                Action::SkippedRow
+            },
+            options.class,
+            catalog,
+            diagnostics,
+        )?;
+        if let Some(label) = label {
+            // Reading the track entries
+            let iter = zip(row, tracks.iter()).enumerate();
+            for (col, (element, column)) in iter {
+                if let Some(column) = column {
+                    // columns with bad headers still contain weights, we skip those but keep iterating to keep the order
+                    let cell = CellRef {
+                        text: element,
+                        position: position(row_number, col + 2),
+                        model: label.models[0],
+                        car: &label.name,
+                        track: &column.name,
+                        from_class: label.from_class,
+                        from_default: column.from_default,
+                    };
+                    // The cell is parsed once, so problems with it are not reported for every car of a class or track
+                    if let Some(entry) = create_entry(&cell, file_type, diagnostics)? {
+                        for track in column.tracks.iter() {
+                            for model in label.models.iter() {
+                                let entry = Entry {
+                                    track: track.clone(),
+                                    car_model: *model,
+                                    ..entry.clone()
+                                };
+                                entries.add(entry, &cell, diagnostics)?;
+                            }
+                        }
+                    }
+                }
+            }
+            count += 1;
+        }
+    }
+    info!("Parsed {} cars", count);
+
+    Ok(entries.entries)
+}
+
+/// Swaps rows and columns, padding short rows with empty cells
+pub fn transpose(table: &[Vec<String>]) -> Vec<Vec<String>> {
+    let width = table.iter().map(|row| row.len()).max().unwrap_or_default();
+    (0..width)
+        .map(|col| {
+            table
+                .iter()
+                .map(|row| row.get(col).cloned().unwrap_or_default())
+                .collect()
+        })
+        .collect()
+}
+
+// A value cell and what it belongs to, so problems with it can be reported
+#[derive(Clone, Copy)]
+struct CellRef<'a> {
+    text: &'a str,
+    position: (usize, usize),
+    model: u32,
+    // The name of the car, for the messages
+    car: &'a str,
+    track: &'a str,
+    // The cell is in a row for all cars of a class (like "@GT4")
+    from_class: bool,
+    // The cell is in the column for all tracks (like "*")
+    from_default: bool,
+}
+
+impl CellRef<'_> {
+    // Cells for a single car win over class rows, then cells for a single track over the default column
+    fn rank(&self) -> (bool, bool) {
+        (self.from_class, self.from_default)
+    }
+}
+
+// (row, column) of a cell
+type Position = (usize, usize);
+
+// The entries of one sheet, remembering where each track and car was set to find duplicates
+struct EntryList {
+    entries: Vec<Entry>,
+    // (track, car_model) -> where the entry was set
+    seen: HashMap<(String, u32), Seen>,
+    policy: DuplicatePolicy,
+}
+
+#[derive(Clone, Copy)]
+struct Seen {
+    // index in entries
+    index: usize,
+    // position of the cell, None while only blank cells were found
+    position: Option<Position>,
+    // see CellRef::rank, None while only blank cells were found
+    rank: Option<(bool, bool)>,
+}
+
+impl EntryList {
+    fn new(policy: DuplicatePolicy) -> EntryList {
+        EntryList {
+            entries: vec![],
+            seen: HashMap::new(),
+            policy,
+        }
+    }
+
+    fn add(&mut self, entry: Entry, cell: &CellRef, diagnostics: &mut Diagnostics) -> Result<()> {
+        // Blank cells don't set anything, so they can't conflict.
+        // A 0 is a value like any other, it clears what an earlier sheet or a less specific cell set.
+        let is_blank = entry.ballast_kg.is_none() && entry.restrictor.is_none();
+        let key = (entry.track.clone(), entry.car_model);
+        let seen = match self.seen.get(&key) {
+            None => {
+                let seen = Seen {
+                    index: self.entries.len(),
+                    position: (!is_blank).then_some(cell.position),
+                    rank: (!is_blank).then_some(cell.rank()),
+                };
+                self.seen.insert(key, seen);
+                self.entries.push(entry);
+                return Ok(());
+            }
+            Some(_) if is_blank => return Ok(()),
+            Some(seen) => *seen,
+        };
+        let index = seen.index;
+        let replaced = Seen {
+            index,
+            position: Some(cell.position),
+            rank: Some(cell.rank()),
+        };
+
+        // The more specific cell wins, no matter which comes first
+        let first = match seen.rank {
+            Some(rank) if cell.rank() > rank => return Ok(()),
+            Some(rank) if cell.rank() == rank => seen.position,
+            _ => None,
+        };
+        let Some(first) = first else {
+            self.entries[index] = entry;
+            self.seen.insert(key, replaced);
+            return Ok(());
+        };
+
+        diagnostics.push(
+            Kind::Duplicate,
+            cell.position,
+            cell.text,
+            format!(
+                "Car {} at track {} was already set in row {}, column {}",
+                cell.car, cell.track, first.0, first.1
+            ),
+            match self.policy {
+                DuplicatePolicy::Error => Action::Aborted,
+                DuplicatePolicy::FirstWins => Action::Ignored,
+                DuplicatePolicy::LastWins => Action::Replaced,
+                DuplicatePolicy::Sum => Action::Summed,
+            },
+        )?;
+
+        match self.policy {
+            DuplicatePolicy::Error => {
+                return Err(Error::Duplicate {
+                    row: cell.position.0,
+                    column: cell.position.1,
+                    text: cell.text.to_string(),
+                })
+            }
+            DuplicatePolicy::FirstWins => (),
+            DuplicatePolicy::LastWins => {
+                self.entries[index] = entry;
+                self.seen.insert(key, replaced);
+            }
+            DuplicatePolicy::Sum => {
+                let (ballast, restrictor) =
+                    sum_values(&self.entries[index], &entry, cell, diagnostics)?;
+                self.entries[index].ballast_kg = ballast;
+                self.entries[index].restrictor = restrictor;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Adds up the values of two entries, the sums are clamped again
+fn sum_values(
+    a: &Entry,
+    b: &Entry,
+    cell: &CellRef,
+    diagnostics: &mut Diagnostics,
+) -> Result<(Option<i32>, Option<i32>)> {
+    let sum = |a: Option<i32>, b: Option<i32>| match (a, b) {
+        // Values of a bop.json aren't limited, saturating keeps huge ones from overflowing before they are clamped
+        (Some(a), Some(b)) => Some(a.saturating_add(b)),
+        (a, b) => a.or(b),
+    };
+    let ballast = match sum(a.ballast_kg, b.ballast_kg) {
+        Some(weight) => clamp_ballast(weight, cell, diagnostics)?,
+        None => None,
+    };
+    let restrictor = match sum(a.restrictor, b.restrictor) {
+        Some(rest) => clamp_restrictor(rest, cell, diagnostics)?,
+        None => None,
+    };
+    Ok((ballast, restrictor))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayerMode {
+    // Values of the layer replace the earlier ones, a 0 clears them and empty cells keep them
+    #[default]
+    Replace,
+    // Values of the layer are added to the earlier ones, the sums are clamped again
+    Add,
+}
+
+/// Applies the entries of a sheet on top of the entries of the sheets read before it, matching them by track and car.
+/// Entries that are new are added. Sums that had to be clamped are recorded in the diagnostics without a cell position.
+pub fn apply_layer(
+    entries: &mut Vec<Entry>,
+    layer: Vec<Entry>,
+    mode: LayerMode,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<()> {
+    let mut index: HashMap<(String, u32), usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| ((entry.track.clone(), entry.car_model), i))
+        .collect();
+
+    for item in layer {
+        let key = (item.track.clone(), item.car_model);
+        let Some(&i) = index.get(&key) else {
+            // no match found, adding this as a new entry
+            index.insert(key, entries.len());
+            entries.push(item);
+            continue;
+        };
+
+        let existing = &mut entries[i];
+        match mode {
+            LayerMode::Replace => {
+                if item.ballast_kg.is_some() {
+                    existing.ballast_kg = item.ballast_kg;
+                }
+                if item.restrictor.is_some() {
+                    existing.restrictor = item.restrictor;
+                }
+            }
+            LayerMode::Add => {
+                let (ballast, restrictor) = sum_entries(existing, &item, catalog, diagnostics)?;
+                existing.ballast_kg = ballast;
+                existing.restrictor = restrictor;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Removes the values of 0 and the entries left without any bop adjustment.
+/// The parse functions keep a 0 to clear earlier layers, this has to run before the entries are written to a bop.json.
+pub fn remove_unadjusted(entries: Vec<Entry>) -> Vec<Entry> {
+    entries
+        .into_iter()
+        .map(|mut entry| {
+            entry.ballast_kg = entry.ballast_kg.filter(|weight| *weight != 0);
+            entry.restrictor = entry.restrictor.filter(|rest| *rest != 0);
+            entry
+        })
+        .filter(|entry| entry.ballast_kg.is_some() || entry.restrictor.is_some())
+        .collect()
+}
+
+// Like sum_values, for entries that don't come from a cell of a sheet
+pub(crate) fn sum_entries(
+    a: &Entry,
+    b: &Entry,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<(Option<i32>, Option<i32>)> {
+    let car_name = catalog
+        .get_car_name_from_id(b.car_model)
+        .unwrap_or(b.car_model.to_string());
+    let text = format!(
+        "{}kg/{}% + {}kg/{}%",
+        a.ballast_kg.unwrap_or(0),
+        a.restrictor.unwrap_or(0),
+        b.ballast_kg.unwrap_or(0),
+        b.restrictor.unwrap_or(0)
+    );
+    let cell = CellRef {
+        text: &text,
+        position: (0, 0),
+        model: b.car_model,
+        car: &car_name,
+        track: &b.track,
+        from_class: false,
+        from_default: false,
+    };
+    sum_values(a, b, &cell, diagnostics)
+}
+
+// Values can carry a unit ("15kg", "3%"), which allows one cell to hold both ("15kg/3%").
+// Values without a unit are read as the type of the file, combined files default to ballast.
+// Returns None if the cell can't be parsed.
+fn create_entry(
+    cell: &CellRef,
+    file_type: BopType,
+    diagnostics: &mut Diagnostics,
+) -> Result<Option<Entry>> {
+    let (ballast, restrictor) = match parse_cell(cell.text, file_type) {
+        Some(values) => values,
+        None => {
+            diagnostics.push(
+                Kind::UnparsableCell,
+                cell.position,
+                cell.text,
+                format!(
+                    "Unable to parse value for car {} at track {}",
+                    cell.car, cell.track
+                ),
+                Action::SkippedCell,
+            )?;
+            return Ok(None);
+        }
+    };
+
+    let ballast = match ballast {
+        Some(weight) => clamp_ballast(weight, cell, diagnostics)?,
+        None => None,
+    };
+    let restrictor = match restrictor {
+        Some(rest) => clamp_restrictor(rest, cell, diagnostics)?,
+        None => None,
+    };
+
+    trace!(
+        "car {} ({}) at {}: {}kg, {}% Restrictor",
+        cell.car,
+        cell.model,
+        cell.track,
+        ballast.unwrap_or(0),
+        restrictor.unwrap_or(0)
+    );
+
+    Ok(Some(Entry {
+        track: cell.track.to_string(),
+        car_model: cell.model,
+        ballast_kg: ballast,
+        restrictor,
+    }))
+}
+
+// Splits the cell into (ballast, restrictor), None if the cell can't be parsed
+fn parse_cell(text: &str, file_type: BopType) -> Option<(Option<i32>, Option<i32>)> {
+    let mut ballast = None;
+    let mut restrictor = None;
+    for part in text.split('/') {
+        let part = part.trim().to_lowercase();
+        if part.is_empty() {
+            // Empty cells are read as 0
+            continue;
+        }
+
+        let (number, is_restrictor) = if let Some(number) = part.strip_suffix("kg") {
+            (number, false)
+        } else if let Some(number) = part.strip_suffix('%') {
+            (number, true)
+        } else {
+            (part.as_str(), file_type == BopType::Restrictor)
+        };
+
+        let value = i32::from_str(number.trim()).ok()?;
+        let target = if is_restrictor {
+            &mut restrictor
+        } else {
+            &mut ballast
+        };
+        if target.replace(value).is_some() {
+            // Two values of the same kind, we can't know which one is meant
+            return None;
+        }
+    }
+
+    Some((ballast, restrictor))
+}
+
+fn clamp_ballast(
+    weight: i32,
+    cell: &CellRef,
+    diagnostics: &mut Diagnostics,
+) -> Result<Option<i32>> {
+    // A 0 is kept so it can clear the value of an earlier layer, it is dropped before writing the bop
+    let clamped = if weight > MAX_BALLAST_KG {
+        MAX_BALLAST_KG
+    } else if weight < -MAX_BALLAST_KG {
+        -MAX_BALLAST_KG
+    } else {
+        return Ok(Some(weight));
+    };
+
+    diagnostics.push(
+        Kind::OutOfRange,
+        cell.position,
+        cell.text,
+        format!(
+            "Weight for car {} at track {} exceeded {}kg ({})",
+            cell.car, cell.track, clamped, weight
+        ),
+        Action::Clamped(clamped),
+    )?;
+    Ok(Some(clamped))
+}
+
+fn clamp_restrictor(
+    rest: i32,
+    cell: &CellRef,
+    diagnostics: &mut Diagnostics,
+) -> Result<Option<i32>> {
+    if rest < 0 {
+        diagnostics.push(
+            Kind::OutOfRange,
+            cell.position,
+            cell.text,
+            format!(
+                "Restrictor for car {} at track {} was less then 0% ({}%), no Restrictor will be applied",
+                cell.car, cell.track, rest
+            ),
+            Action::Ignored,
+        )?;
+        Ok(None)
+    } else if rest > MAX_RESTRICTOR {
+        diagnostics.push(
+            Kind::OutOfRange,
+            cell.position,
+            cell.text,
+            format!(
+                "Restrictor for car {} at track {} exceeded {}% ({}%)",
+                cell.car, cell.track, MAX_RESTRICTOR, rest
+            ),
+            Action::Clamped(MAX_RESTRICTOR),
+        )?;
+        Ok(Some(MAX_RESTRICTOR))
+    } else {
+        Ok(Some(rest))
+    }
+}
+
+// The tracks a label in the track column stands for
+struct TrackLabel {
+    tracks: Vec<String>,
+    // The track or column name, for the messages
+    name: String,
+    from_default: bool,
+}
+
+// "*" or "default" stands for every track of the catalog, anything else for a single track.
+// None if the label could not be resolved, this was reported.
+fn resolve_track_label(
+    text: &str,
+    position: Position,
+    action: Action,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<Option<TrackLabel>> {
+    let text = text.trim();
+    if text == "*" || text.eq_ignore_ascii_case("default") {
+        trace!("Found default column {}", text);
+        return Ok(Some(TrackLabel {
+            tracks: catalog.tracks.clone(),
+            name: text.to_string(),
+            from_default: true,
+        }));
+    }
+
+    let track = resolve_track(text, position, action, catalog, diagnostics)?;
+    Ok(track.map(|track| TrackLabel {
+        tracks: vec![track.clone()],
+        name: track,
+        from_default: false,
+    }))
+}
+
+// Like validate_track, but corrects typos and reports tracks it can't find
+fn resolve_track(
+    text: &str,
+    position: Position,
+    action: Action,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<Option<String>> {
+    if let Some(track) = catalog.validate_track(text) {
+        return Ok(Some(track));
+    }
+
+    if let Some(track) = catalog.correct_track(text) {
+        diagnostics.push(
+            Kind::Misspelled,
+            position,
+            text,
+            format!("Unknown track, using {}", track),
+            Action::Corrected,
+        )?;
+        return Ok(Some(track));
+    }
+
+    diagnostics.push(
+        Kind::UnknownTrack,
+        position,
+        text,
+        format!(
+            "Unable to parse track{}",
+            did_you_mean(&catalog.suggest_tracks(text))
+        ),
+        action,
+    )?;
+    Ok(None)
+}
+
+// The cars a label in the car column stands for
+struct CarLabel {
+    models: Vec<u32>,
+    // The name of the car or class, for the messages
+    name: String,
+    from_class: bool,
+}
+
+// A class selector (like "@GT4" or "class:GT3") stands for all cars of the class, anything else for a single car.
+// None if the label could not be resolved, this was reported.
+fn resolve_car_label(
+    label: &str,
+    position: Position,
+    action: Action,
+    class: Option<CarClass>,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<Option<CarLabel>> {
+    let Some(selector) = class_selector(label) else {
+        let model = resolve_car_model(label, position, action, class, catalog, diagnostics)?;
+        return Ok(model.map(|model| CarLabel {
+            models: vec![model],
+            name: catalog
+                .get_car_name_from_id(model)
+                .unwrap_or(model.to_string()),
+            from_class: false,
+        }));
+    };
+
+    let Some(selected) = CarClass::from_name(selector) else {
+        let names: Vec<String> = CarClass::ALL
+            .iter()
+            .map(|class| class.to_string())
+            .collect();
+        diagnostics.push(
+            Kind::UnknownCar,
+            position,
+            label,
+            format!("Unable to parse car class, known are {}", names.join(", ")),
+            action,
+        )?;
+        return Ok(None);
+    };
+    if let Some(class) = class.filter(|class| *class != selected) {
+        // Filtered out on purpose, so this is no problem of the sheet
+        info!(
+            "Skipping '{}' in row {}, only {} cars are read",
+            label.trim(),
+            position.0,
+            class
+        );
+        return Ok(None);
+    }
+
+    let models: Vec<u32> = catalog.cars_of_class(selected).map(|car| car.id).collect();
+    if models.is_empty() {
+        info!("The catalog has no {} cars", selected);
+        return Ok(None);
+    }
+    info!("Found class {} ({} cars)", selected, models.len());
+    Ok(Some(CarLabel {
+        models,
+        name: format!("class {}", selected),
+        from_class: true,
+    }))
+}
+
+// The class name of a class selector ("GT4" of "@GT4" or "class:GT4"), None if the label is no selector
+pub(crate) fn class_selector(label: &str) -> Option<&str> {
+    let label = label.trim();
+    label.strip_prefix('@').or_else(|| {
+        label
+            .get(..6)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("class:"))
+            .map(|_| &label[6..])
+    })
+}
+
+pub(crate) fn did_you_mean(names: &[String]) -> String {
+    if names.is_empty() {
+        String::new()
+    } else {
+        format!(", did you mean {}?", names.join(", "))
+    }
+}
+
+// Like validate_car_model, but corrects typos and reports labels that match no or more than one car.
+// The first candidate is used, just like before the check existed.
+// Cars outside the class are skipped, they are only logged since the user asked to leave them out.
+fn resolve_car_model(
+    label: &str,
+    position: Position,
+    action: Action,
+    class: Option<CarClass>,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<Option<u32>> {
+    let mut candidates = catalog.find_car_models(label);
+    if candidates.is_empty() {
+        if let Some(corrected) = catalog.correct_car_model(label) {
+            candidates = catalog.find_car_models(&corrected);
+            if let Some(&id) = candidates.first() {
+                diagnostics.push(
+                    Kind::Misspelled,
+                    position,
+                    label,
+                    format!("Unknown car model, read as '{}'", corrected),
+                    Action::Matched(id),
+                )?;
+            }
+        }
+    }
+
+    let Some(&id) = candidates.first() else {
+        let names: Vec<String> = catalog
+            .suggest_car_models(label)
+            .into_iter()
+            .map(|id| catalog.get_car_name_from_id(id).unwrap_or_default())
+            .collect();
+        diagnostics.push(
+            Kind::UnknownCar,
+            position,
+            label,
+            format!("Unable to parse car model{}", did_you_mean(&names)),
+            action,
+        )?;
+        return Ok(None);
+    };
+
+    if candidates.len() > 1 {
+        let names: Vec<String> = candidates
+            .iter()
+            .map(|id| {
+                format!(
+                    "{} ({})",
+                    catalog.get_car_name_from_id(*id).unwrap_or_default(),
+                    id
+                )
+            })
+            .collect();
+        diagnostics.push(
+            Kind::AmbiguousCar,
+            position,
+            label,
+            format!(
+                "Car model matches {}, add the id in brackets (like '{} [{}]') to pick one",
+                names.join(", "),
+                label.trim(),
+                id
+            ),
+            Action::Matched(id),
+        )?;
+    }
+
+    if let Some(class) = class {
+        let car_class = catalog.get_car_class(id);
+        if car_class != Some(class) {
+            info!(
+                "Skipping car {} in row {}, it is a {} car, not {}",
+                catalog.get_car_name_from_id(id).unwrap_or_default(),
+                position.0,
+                car_class.map(|class| class.to_string()).unwrap_or_default(),
+                class
+            );
+            return Ok(None);
+        }
+    }
+
+    info!(
+        "Found car {} ({})",
+        catalog.get_car_name_from_id(id).unwrap_or_default(),
+        id
+    );
+    Ok(Some(id))
+}
+
+/// Reads a bop.json, in any of the encodings read_file detects
+pub fn read_bop(path: &PathBuf) -> Result<BOP> {
+    info!("Reading {}...", path.display());
+
+    let content = read_file(path)?;
+
+    trace!("Parsing File...");
+
+    Ok(serde_json::from_str(content.as_str())?)
+}
+
+pub fn bop2csv(
+    bop_json: String,
+    output: Option<String>,
+    overwrite: OverwritePolicy,
+    options: CsvOptions,
+    catalog: &Catalog,
+) -> Result<()> {
+    let mut entries = read_bop(&PathBuf::from(&bop_json))?.entries;
+    if let Some(class) = options.class {
+        entries.retain(|entry| catalog.get_car_class(entry.car_model) == Some(class));
+        info!("Writing the {} entries of {} cars", entries.len(), class);
+    }
+
+    if options.format == Some(TableFormat::Long) {
+        return write_long(&entries, output, overwrite, options);
+    }
+
+    trace!("Finished Parsing json, converting to table...");
+
+    // Parsing the entries into a table
+    let mut table: HashMap<String, Vec<Option<Entry>>> = HashMap::new();
+    let mut row_label: Vec<u32> = vec![];
+
+    for item in entries {
+        let mut row_index = 0;
+        for i in row_label.iter() {
+            if i == &item.car_model {
+                break;
+            }
+            row_index += 1;
+        }
+        if row_index == row_label.len() {
+            row_label.push(item.car_model);
+        }
+
+        let column = table
+            .entry(item.track.clone())
+            .or_insert_with(|| Vec::<Option<Entry>>::with_capacity(row_label.len() + 1));
+        while column.len() <= row_index {
+            column.push(None);
+        }
+
+        column[row_index] = Some(item);
+    }
+
+    // Column headers
+    let mut column_headers = Vec::<String>::with_capacity(23);
+    for (c, _) in table.iter() {
+        column_headers.push(c.to_string());
+    }
+    column_headers.sort();
+
+    // Writing down the entries into Rows
+    let mut rows = Vec::<Vec<Option<Entry>>>::with_capacity(row_label.len());
+    let mut row_human_label = Vec::<String>::with_capacity(row_label.len());
+    for row in row_label {
+        row_human_label.push(catalog.get_car_name_from_id(row).unwrap_or(row.to_string()));
+
+        let index = rows.len();
+        let mut row_items = Vec::<Option<Entry>>::with_capacity(column_headers.len());
+
+        for col in column_headers.iter() {
+            let col = &table[col];
+            if col.len() > index {
+                row_items.push(col[index].clone());
+            } else {
+                row_items.push(None);
+            }
+        }
+
+        rows.push(row_items);
+    }
+
+    trace!("Finished Tableizing");
+
+    // Workbooks get both tables as sheets in one file
+    if let Some(target) = output.as_ref().map(PathBuf::from) {
+        if workbook::is_workbook(&target) {
+            return write_workbook(
+                &column_headers,
+                &row_human_label,
+                &rows,
+                target,
+                overwrite,
+                options,
+            );
+        }
+    }
+
+    if options.combined {
+        let mut path = PathBuf::from(output.unwrap_or("bop.csv".to_string()));
+        if path.is_dir() {
+            path.push("bop.csv");
+        }
+
+        return write_csv(
+            &column_headers,
+            &row_human_label,
+            &rows,
+            path,
+            BopType::Combined,
+            overwrite,
+            options,
+        );
+    }
+
+    // Output path
+    let (ballast_path, restrictor_path) = if let Some(target) = output {
+        let mut target = PathBuf::from(target);
+
+        if target.is_dir() {
+            // User defined a folder... perfect, we just put both files into there
+            let mut restrictor = target.clone();
+            target.push("ballast.csv");
+            restrictor.push("restrictor.csv");
+            (target, restrictor)
+        } else {
+            // user defined a file, we will create a restrictor csv in the same folder
+            let mut restrictor = target.clone();
+            restrictor.set_file_name("restrictor.csv");
+            (target, restrictor)
+        }
+    } else {
+        (
+            PathBuf::from("ballast.csv".to_string()),
+            PathBuf::from("restrictor.csv".to_string()),
+        )
+    };
+
+    write_csv(
+        &column_headers,
+        &row_human_label,
+        &rows,
+        ballast_path,
+        BopType::Ballast,
+        overwrite,
+        options,
+    )?;
+    write_csv(
+        &column_headers,
+        &row_human_label,
+        &rows,
+        restrictor_path,
+        BopType::Restrictor,
+        overwrite,
+        options,
+    )
+}
+
+/// Writes the entries in the long format, into a single csv (bop.csv by default) or workbook sheet
+pub fn write_long(
+    entries: &[Entry],
+    output: Option<String>,
+    overwrite: OverwritePolicy,
+    options: CsvOptions,
+) -> Result<()> {
+    let mut table = Vec::<Vec<String>>::with_capacity(entries.len() + 1);
+    table.push(LONG_HEADER.iter().map(|cell| cell.to_string()).collect());
+    for entry in entries {
+        table.push(vec![
+            entry.track.clone(),
+            entry.car_model.to_string(),
+            entry
+                .ballast_kg
+                .map(|val| val.to_string())
+                .unwrap_or_default(),
+            entry
+                .restrictor
+                .map(|val| val.to_string())
+                .unwrap_or_default(),
+        ]);
+    }
+
+    let mut path = PathBuf::from(output.unwrap_or("bop.csv".to_string()));
+    if path.is_dir() {
+        path.push("bop.csv");
+    }
+
+    info!("Writing {}... ", path.display());
+    check_overwrite(&path, overwrite)?;
+    if workbook::is_workbook(&path) {
+        workbook::write_sheets(&path, &[("BOP".to_string(), table)])
+    } else {
+        fs::write(
+            path,
+            csv::write_records(&table, options.delimiter.unwrap_or(',')),
+        )?;
+        Ok(())
+    }
+}
+
+pub fn write_workbook(
+    column_headers: &[String],
+    row_human_label: &[String],
+    rows: &[Vec<Option<Entry>>],
+    path: PathBuf,
+    overwrite: OverwritePolicy,
+    options: CsvOptions,
+) -> Result<()> {
+    let file_types = if options.combined {
+        vec![BopType::Combined]
+    } else {
+        vec![BopType::Ballast, BopType::Restrictor]
+    };
+
+    let mut sheets = Vec::<(String, Vec<Vec<String>>)>::with_capacity(file_types.len());
+    for file_type in file_types {
+        trace!("Producing sheet for {}", file_type);
+        let (table, _) = build_table(column_headers, row_human_label, rows, file_type);
+        sheets.push((file_type.to_string(), orient(table, options)));
+    }
+
+    info!("Writing workbook {}... ", path.display());
+    check_overwrite(&path, overwrite)?;
+    workbook::write_sheets(&path, &sheets)
+}
+
+pub fn write_csv(
+    column_headers: &[String],
+    row_human_label: &[String],
+    rows: &[Vec<Option<Entry>>],
+    path: PathBuf,
+    file_type: BopType,
+    overwrite: OverwritePolicy,
+    options: CsvOptions,
+) -> Result<()> {
+    trace!("Producing csv table for {}", file_type);
+    let (table, contains_anything) = build_table(column_headers, row_human_label, rows, file_type);
+    let table = orient(table, options);
+
+    if contains_anything {
+        info!("Writing {}... ", file_type);
+        check_overwrite(&path, overwrite)?;
+        fs::write(
+            path,
+            csv::write_records(&table, options.delimiter.unwrap_or(',')),
+        )?;
+    } else {
+        trace!(
+            "Skipped writing {}, bop does not contain any changes to it",
+            file_type
+        );
+    }
+
+    Ok(())
+}
+
+// build_table produces the matrix, this flips it if the transposed format was requested
+fn orient(table: Vec<Vec<String>>, options: CsvOptions) -> Vec<Vec<String>> {
+    if options.format == Some(TableFormat::Transposed) {
+        transpose(&table)
+    } else {
+        table
+    }
+}
+
+// The values with their units like in a combined cell (15kg/3%), None if there are none
+pub(crate) fn format_values(ballast: Option<i32>, restrictor: Option<i32>) -> Option<String> {
+    match (ballast, restrictor) {
+        (Some(ballast), Some(restrictor)) => Some(format!("{}kg/{}%", ballast, restrictor)),
+        (Some(ballast), None) => Some(format!("{}kg", ballast)),
+        (None, Some(restrictor)) => Some(format!("{}%", restrictor)),
+        (None, None) => None,
+    }
+}
+
+// Turns the entries into the cells of a table, also returns if any cell contains a bop change
+fn build_table(
+    column_headers: &[String],
+    row_human_label: &[String],
+    rows: &[Vec<Option<Entry>>],
+    file_type: BopType,
+) -> (Vec<Vec<String>>, bool) {
+    let mut table = Vec::<Vec<String>>::with_capacity(rows.len() + 1);
+    let mut header = vec![String::new()];
+    header.extend(column_headers.iter().cloned());
+    table.push(header);
+
+    let mut contains_anything = false;
+
+    for (row_header, row) in zip(row_human_label, rows) {
+        let mut cells = Vec::<String>::with_capacity(row.len() + 1);
+        cells.push(row_header.clone());
+
+        for item in row {
+            let (ballast, restrictor) = item
+                .as_ref()
+                .map(|val| (val.ballast_kg, val.restrictor))
+                .unwrap_or_default();
+
+            let cell = match file_type {
+                BopType::Ballast => ballast.map(|ballast| ballast.to_string()),
+                BopType::Restrictor => restrictor.map(|restrictor| restrictor.to_string()),
+                BopType::Combined => format_values(ballast, restrictor),
+            };
+            contains_anything |= cell.is_some();
+            cells.push(cell.unwrap_or("0".to_string()));
+        }
+
+        table.push(cells);
+    }
+
+    (table, contains_anything)
+}
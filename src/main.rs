@@ -1,30 +1,99 @@
-use std::{fs, iter::zip, path::PathBuf, str::FromStr, collections::HashMap};
-
-use clap::Parser;
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use acc_csv2bop::{
+    apply_layer, bop2csv,
+    data::{Entry, BOP},
+    diagnostics::{Diagnostics, Severity},
+    diff::{self, diff_bops},
+    encoding::TextEncoding,
+    merge::merge_bops,
+    parse_file,
+    patch::{apply_patch, Patch},
+    read_bop, remove_unadjusted, write_bop, Aliases, BopType, CarClass, Catalog, CsvOptions,
+    DuplicatePolicy, LayerMode, OverwritePolicy, TableFormat,
+};
+use clap::{Parser, ValueEnum};
 use dialoguer::Confirm;
-use log::{error, info, trace};
-
-pub mod data;
-use data::{Entry, BOP, CARS, TRACKS};
-
-#[cfg(test)]
-mod test;
+use log::{error, info};
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    #[arg(short, long, help = "ballast csv file (required)")]
-    ballast: Option<String>, // this is an option so we can have the helper outputs for carmodel and tracklists
+    #[arg(
+        short,
+        long,
+        value_parser = parse_layer,
+        help = "ballast csv, .ods or .xlsx file (required), repeat to apply more files on top in order, prefix with add: to add their values instead of replacing"
+    )]
+    ballast: Vec<Layer>, // this may be empty so we can have the helper outputs for carmodel and tracklists
+
+    #[arg(
+        short,
+        long,
+        value_parser = parse_layer,
+        help = "restrictor csv, .ods or .xlsx file (optional), applied after the ballast files and repeatable like --ballast"
+    )]
+    restrictor: Vec<Layer>,
+
+    #[arg(
+        long,
+        help = "sheet to read the ballast from in a workbook, defaults to the sheet named Ballast or the first"
+    )]
+    sheet: Option<String>,
 
-    #[arg(short, long, help = "restrictor csv file (optional)")]
-    restrictor: Option<String>,
+    #[arg(
+        long,
+        help = "sheet to read the restrictor from in a workbook, defaults to the sheet named Restrictor or the first"
+    )]
+    restrictor_sheet: Option<String>,
 
-    #[arg(short, long, help = "output file, defaults to bop.json / ballast.csv")]
+    #[arg(
+        short,
+        long,
+        help = "output file, defaults to bop.json / ballast.csv (use a .ods/.xlsx to get both as sheets of one file)"
+    )]
     output: Option<String>,
 
     #[arg(short, long, help = "A bop.json to parse to CSV file(s)")]
     json: Option<String>,
 
+    #[arg(
+        long,
+        num_args = 1..,
+        conflicts_with = "json",
+        help = "bop.json files to merge into one (--output, defaults to bop.json), see --duplicates for which value wins when they disagree"
+    )]
+    merge: Vec<String>,
+
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["OLD", "NEW"],
+        conflicts_with_all = ["json", "merge"],
+        help = "print the entries added, removed and changed between two bop.json files"
+    )]
+    diff: Vec<String>,
+
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["PATCH", "BOP"],
+        conflicts_with_all = ["json", "merge", "diff"],
+        help = "apply a toml or json patch (see README) to a bop.json, written to --output (defaults to bop.json)"
+    )]
+    apply: Vec<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = DiffFormat::Text,
+        help = "format of the --diff output"
+    )]
+    diff_format: DiffFormat,
+
     #[arg(
         short,
         long,
@@ -32,6 +101,85 @@ pub struct Args {
     )]
     verbose: bool,
 
+    #[arg(
+        short,
+        long,
+        value_parser = parse_delimiter,
+        help = "csv delimiter (',', ';' or 'tab'), detected from the header row if not set"
+    )]
+    delimiter: Option<char>,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "table layout, matrix (tracks in the top row), transposed (cars in the top row) or long (track,car,ballast_kg,restrictor), detected if not set"
+    )]
+    format: Option<Format>,
+
+    #[arg(long, conflicts_with = "format", help = "same as --format transposed")]
+    transpose: bool,
+
+    #[arg(
+        long,
+        help = "write a single table with both ballast and restrictor in each cell (like 15kg/3%) when converting a bop.json"
+    )]
+    combined: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Duplicates::LastWins,
+        help = "what to do when a sheet sets the same car at the same track twice (like both Bathurst and Mount Panorama) or merged files disagree"
+    )]
+    duplicates: Duplicates,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Encoding::Utf8,
+        help = "text encoding of the written bop.json, the ACC server uses utf16le"
+    )]
+    encoding: Encoding,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Overwrite::Prompt,
+        help = "what to do when an output file already exists"
+    )]
+    overwrite: Overwrite,
+
+    #[arg(long, help = "abort without writing on any skipped or clamped value")]
+    strict: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ReportFormat::Text,
+        help = "format of the report of skipped and clamped values, json is printed even if there are none"
+    )]
+    report_format: ReportFormat,
+
+    #[arg(
+        long,
+        help = "toml or json file with additional names for tracks and cars (see README)"
+    )]
+    aliases: Option<String>,
+
+    #[arg(
+        long,
+        help = "json or toml file with all tracks and cars, replaces the built-in list (see README)"
+    )]
+    catalog: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        ignore_case = true,
+        help = "only read, write and list cars of this class, cars of other classes in a sheet are skipped"
+    )]
+    class: Option<Class>,
+
     #[arg(long, help = "list all tracks and exit")]
     list_tracks: bool,
 
@@ -39,557 +187,419 @@ pub struct Args {
     list_carmodels: bool,
 }
 
-fn main() {
-    let args = Args::parse();
-    let log_level = if args.verbose {
-        log::LevelFilter::Trace
-    } else {
-        log::LevelFilter::Info
-    };
-    env_logger::builder().filter_level(log_level).init();
+// Exit codes, so scripts can tell if the bop.json is complete
+const EXIT_ERROR: u8 = 1;
+const EXIT_WARNING: u8 = 2;
 
-    // Handling the lists
-    if args.list_carmodels {
-        println!("All Cars (printed pseudo alphabetical order, this is the order the lookup operation uses):");
-        for (id, item) in CARS {
-            println!("{}: {}", item, id);
-        }
-        return;
-    }
-    if args.list_tracks {
-        println!("All Tracks:");
-        for item in TRACKS {
-            println!("{}", item);
-        }
-        return;
-    }
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Overwrite {
+    Prompt,
+    Force,
+    Never,
+    Backup,
+}
 
-    // bop 2 csv
-    if let Some(bop) = args.json {
-        if bop2csv(bop, args.output).is_none() {
-            error!("Failed to parse bop to csv, exiting...");
+impl From<Overwrite> for OverwritePolicy {
+    fn from(value: Overwrite) -> Self {
+        match value {
+            Overwrite::Prompt => OverwritePolicy::Prompt(confirm_overwrite),
+            Overwrite::Force => OverwritePolicy::Force,
+            Overwrite::Never => OverwritePolicy::Never,
+            Overwrite::Backup => OverwritePolicy::Backup,
         }
-        info!("Finished Writing");
-        return;
     }
+}
 
-    // Verifying that ballast path is present
-    let ballast_file = if let Some(bal) = args.ballast {
-        bal
-    } else {
-        error!("Ballast is required! See --help for futher info");
-        return;
-    };
-
-    // Setting output file
-    let path = PathBuf::from(if let Some(target) = args.output {
-        target
-    } else {
-        "bop.json".to_string()
-    });
-
-    if path.is_dir() {
-        error!("Output path is a folder, please point at a File!");
-        return;
-    }
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Matrix,
+    Long,
+    Transposed,
+}
 
-    if path.exists() {
-        if !Confirm::new()
-            .with_prompt(format!(
-                "File {} already exists. Override?",
-                path.clone().to_str().expect("it is a string")
-            ))
-            .default(false)
-            .interact()
-            .unwrap_or(false)
-        {
-            info!("Unable to Save, Exiting...");
-            return;
+impl From<Format> for TableFormat {
+    fn from(value: Format) -> Self {
+        match value {
+            Format::Matrix => TableFormat::Matrix,
+            Format::Long => TableFormat::Long,
+            Format::Transposed => TableFormat::Transposed,
         }
     }
+}
 
-    // Getting the Ballast
-    if let Some(mut res) = parse_csv(ballast_file, BopType::Ballast) {
-        // Getting the restrictor
-        if let Some(rest_file) = args.restrictor {
-            if let Some(rest_res) = parse_csv(rest_file, BopType::Restrictor) {
-                // Merging the two lists
-                for item in rest_res {
-                    let mut index = 0;
-                    for ent in res.iter() {
-                        if item.track == ent.track && item.car_model == ent.car_model {
-                            break;
-                        }
-                        index += 1;
-                    }
-
-                    if index >= res.len() {
-                        // no match found, adding this as a new entry
-                        res.push(item);
-                    } else {
-                        res[index].restrictor = item.restrictor;
-                    }
-                }
-            } else {
-                error!("Unable to parse restrictor csv, exiting...");
-                return;
-            }
-        }
-
-        // Removing entries with no bop adjustment
-        let mut entries = Vec::<Entry>::with_capacity(res.len());
-        for item in res {
-            if item.ballast_kg.is_some() || item.restrictor.is_some() {
-                entries.push(item);
-            }
-        }
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Duplicates {
+    Error,
+    FirstWins,
+    LastWins,
+    Sum,
+}
 
-        if let Ok(json) = serde_json::to_string_pretty(&BOP { entries }) {
-            
-            if fs::write(&path, json).is_ok() {
-                info!(
-                    "Finished writing to {}",
-                    path.to_str().expect("it is a string")
-                );
-                return;
-            }
+impl From<Duplicates> for DuplicatePolicy {
+    fn from(value: Duplicates) -> Self {
+        match value {
+            Duplicates::Error => DuplicatePolicy::Error,
+            Duplicates::FirstWins => DuplicatePolicy::FirstWins,
+            Duplicates::LastWins => DuplicatePolicy::LastWins,
+            Duplicates::Sum => DuplicatePolicy::Sum,
         }
-
-        error!("Failed to write {}", path.to_str().expect("it is a string"));
-    } else {
-        error!("Unable to parse ballast csv, exiting...");
     }
 }
 
-#[derive()]
-pub enum BopType {
-    Ballast,
-    Restrictor,
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Class {
+    #[value(name = "GT3")]
+    Gt3,
+    #[value(name = "GT4")]
+    Gt4,
+    #[value(name = "GT2")]
+    Gt2,
+    #[value(name = "GTC")]
+    Gtc,
+    #[value(name = "TCX")]
+    Tcx,
 }
 
-impl ToString for BopType {
-    fn to_string(&self) -> String {
-        match self {
-            BopType::Ballast => "Ballast",
-            BopType::Restrictor => "Restrictor",
+impl From<Class> for CarClass {
+    fn from(value: Class) -> Self {
+        match value {
+            Class::Gt3 => CarClass::Gt3,
+            Class::Gt4 => CarClass::Gt4,
+            Class::Gt2 => CarClass::Gt2,
+            Class::Gtc => CarClass::Gtc,
+            Class::Tcx => CarClass::Tcx,
         }
-        .to_string()
     }
 }
 
-pub fn parse_csv(csv_file_path: String, file_type: BopType) -> Option<Vec<Entry>> {
-    let path = PathBuf::from_str(&csv_file_path).ok()?;
-
-    if !path.is_file() {
-        error!("File does not exist!");
-        return None;
-    }
-
-    info!("Loading {} file {}", file_type.to_string(), &csv_file_path);
-
-    let raw = fs::read(path).ok()?;
-    let content = String::from_utf8(raw).ok()?;
-    let file = content.replace("\u{0}", "");
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ReportFormat {
+    Text,
+    Json,
+}
 
-    trace!("Parsing data...");
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum DiffFormat {
+    Text,
+    Markdown,
+    Json,
+}
 
-    let mut file = file.split("\n");
-    let mut toprow = file.next()?.trim().split(",");
-    toprow.next()?;
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Encoding {
+    Utf8,
+    Utf16le,
+}
 
-    let mut tracks: Vec<Option<String>> = vec![];
-    for element in toprow {
-		let element = element.trim();
-        if let Some(track) = validate_track(element) {
-            tracks.push(Some(track));
-        } else {
-            error!("Unable to parse track '{}', skipping", element);
-            tracks.push(None); // This has to be an option, as we need to later be able to keep the columns intact for the weights
+impl From<Encoding> for TextEncoding {
+    fn from(value: Encoding) -> Self {
+        match value {
+            Encoding::Utf8 => TextEncoding::Utf8,
+            Encoding::Utf16le => TextEncoding::Utf16Le,
         }
     }
-    info!("Found {} tracks", tracks.len());
-
-    let mut entries: Vec<Entry> = vec![];
-    let mut count = 0;
-    for car in file {
-        let test = car.replace(",", "");
-        if !test.trim().is_empty() {
-            let mut row = car.trim().split(",");
-            if let Some(model) = validate_car_model(row.next()) {
-                // Reading the track entries
-                let iter = zip(row, tracks.iter());
-                for (element, track) in iter {
-					let element = element.trim();
-                    if let Some(track) = track {
-                        // columns with bad headers still contain weights, we skip those but keep iterating to keep the order
-                        entries.push(match file_type {
-                            BopType::Ballast => create_ballast_entry(element, model, track),
-                            BopType::Restrictor => create_restrictor_entry(element, model, track),
-                        });
-                    }
-                }
-                count += 1;
-            }
-        }
-    }
-    info!("Parsed {} cars", count);
+}
+
+fn confirm_overwrite(path: &Path) -> bool {
+    Confirm::new()
+        .with_prompt(format!("File {} already exists. Override?", path.display()))
+        .default(false)
+        .interact()
+        .unwrap_or(false)
+}
 
-    Some(entries)
+// A sheet given with --ballast or --restrictor, like "add:hotfix.csv"
+#[derive(Debug, Clone)]
+struct Layer {
+    path: String,
+    mode: LayerMode,
 }
 
-fn create_ballast_entry(weight_string: &str, model: u32, track: &String) -> Entry {
-    let weight_string = weight_string.trim();
-    let weight_string = if weight_string.is_empty() {
-        // this is done so we can error when the parse failed without erroring on empty
-        "0"
+fn parse_layer(value: &str) -> Result<Layer, String> {
+    let (path, mode) = if let Some(path) = value.strip_prefix("add:") {
+        (path, LayerMode::Add)
+    } else if let Some(path) = value.strip_prefix("replace:") {
+        (path, LayerMode::Replace)
     } else {
-        weight_string
+        (value, LayerMode::Replace)
     };
+    if path.is_empty() {
+        return Err("file path is missing".to_string());
+    }
+    Ok(Layer {
+        path: path.to_string(),
+        mode,
+    })
+}
 
-    let car_name = get_car_name_from_id(model).unwrap_or(model.to_string());
-
-    let weight = if let Ok(weight) = i32::from_str(weight_string) {
-        if weight == 0 {
-            None // Allows us to drop the entry later when excluding those without any adjustments
-        } else if weight > 40 {
-            error!(
-                "Weight for car {} at track {} exceeded 40kg ({}), using 40kg",
-                car_name, track, weight
-            );
-            Some(40)
-        } else if weight < -40 {
-            error!(
-                "Weight for car {} at track {} exceeded -40kg ({}), using -40kg",
-                car_name, track, weight
-            );
-            Some(-40)
-        } else {
-            Some(weight)
+fn parse_delimiter(value: &str) -> Result<char, String> {
+    match value {
+        "tab" | "\\t" | "\t" => Ok('\t'),
+        _ => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err("delimiter has to be a single character or 'tab'".to_string()),
+            }
         }
-    } else {
-        error!(
-            "Failed to parse weight for car {} at track {}. String was {}. Skipping",
-            car_name, track, weight_string
-        );
-        None
-    };
-    trace!(
-        "car {} ({}) at {}: {}kg",
-        car_name,
-        model,
-        track,
-        weight.unwrap_or(0)
-    );
-
-    let entry = Entry {
-        track: track.clone(),
-        car_model: model,
-        ballast_kg: weight,
-        restrictor: None,
-    };
-    entry
+    }
 }
 
-fn create_restrictor_entry(restrictor_string: &str, model: u32, track: &String) -> Entry {
-    let restrictor_string = restrictor_string.trim();
-    let restrictor_string = if restrictor_string.is_empty() {
-        // this is done so we can error when the parse failed without erroring on empty
-        "0"
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let log_level = if args.verbose {
+        log::LevelFilter::Trace
     } else {
-        restrictor_string
+        log::LevelFilter::Info
     };
+    env_logger::builder().filter_level(log_level).init();
 
-    let car_name = get_car_name_from_id(model).unwrap_or(model.to_string());
-
-    let rest = if let Ok(rest) = i32::from_str(restrictor_string) {
-        if rest == 0 {
-            None
-        } else if rest < 0 {
-            error!(
-                "Restrictor for car {} at track {} was less then 0% ({}%), no Restrictor will be applied",
-                car_name, track, rest
-            );
-            None
-        } else if rest > 20 {
-            error!(
-                "Restrictor for car {} at track {} exceeded 20% ({}%), using 20%",
-                car_name, track, rest
-            );
-            Some(20)
+    let csv_options = CsvOptions {
+        delimiter: args.delimiter,
+        combined: args.combined,
+        format: if args.transpose {
+            Some(TableFormat::Transposed)
         } else {
-            Some(rest)
-        }
-    } else {
-        error!(
-            "Failed to parse restrictor for car {} at track {}. String was {}. Skipping",
-            car_name, track, restrictor_string
-        );
-        None
+            args.format.map(TableFormat::from)
+        },
+        duplicates: args.duplicates.into(),
+        class: args.class.map(CarClass::from),
     };
 
-    trace!(
-        "car {} ({}) at {}: {}% Restrictor",
-        car_name,
-        model,
-        track,
-        rest.unwrap_or(0)
-    );
-
-    let entry = Entry {
-        track: track.clone(),
-        car_model: model,
-        ballast_kg: None,
-        restrictor: rest,
+    let mut catalog = match args.catalog {
+        Some(path) => match Catalog::load(&PathBuf::from(path)) {
+            Ok(catalog) => catalog,
+            Err(e) => {
+                error!("Unable to load catalog: {}, exiting...", e);
+                return ExitCode::from(EXIT_ERROR);
+            }
+        },
+        None => Catalog::default(),
     };
-    entry
-}
 
-pub fn validate_track(track_str: &str) -> Option<String> {
-    let track_str = track_str
-        .replace(" ", "_")
-        .to_lowercase()
-        .replace("bathurst", "mount_panorama")
-        .replace("redbull_ring", "red_bull_ring")
-        .replace("nordschleife", "nurburgring_24h");
-
-    for item in TRACKS {
-        if item.eq_ignore_ascii_case(track_str.as_str()) {
-            trace!("Found Track {}", item);
-            return Some(item.to_string());
+    if let Some(path) = args.aliases {
+        let aliases = Aliases::load(&PathBuf::from(path));
+        if let Err(e) = aliases.and_then(|aliases| catalog.add_aliases(aliases)) {
+            error!("Unable to load aliases: {}, exiting...", e);
+            return ExitCode::from(EXIT_ERROR);
         }
     }
 
-    None
-}
-
-pub fn validate_car_model(model_str: Option<&str>) -> Option<u32> {
-    if let Some(text) = model_str {
-		let text = text.trim();
-        // Finding based on ID
-        if let Some(id) = u32::from_str(text).ok() {
-            if let Some(car_name) = get_car_name_from_id(id) {
-                info!("Found car {} ({})", car_name, id);
-                return Some(id);
+    // Handling the lists
+    if args.list_carmodels {
+        println!("All Cars (printed pseudo alphabetical order, this is the order the lookup operation uses):");
+        let cars = catalog.cars.iter().filter(|car| {
+            csv_options
+                .class
+                .map(|class| car.class == class)
+                .unwrap_or(true)
+        });
+        for car in cars {
+            let names = catalog.aliases.car_aliases(car.id);
+            if names.is_empty() {
+                println!("{}: {} ({})", car.name, car.id, car.class);
             } else {
-                error!("No car is known to have id {}", id)
+                println!(
+                    "{}: {} ({}, aliases: {})",
+                    car.name,
+                    car.id,
+                    car.class,
+                    names.join(", ")
+                );
             }
         }
-
-        // We try to find the car based on the name, specifically we turn the text into tokens and then see if one carname contains all tokens
-        let keywords: Vec<&str> = text
-            .split(" ")
-            .filter(|sample| !sample.trim().is_empty())
-            .collect();
-        if !keywords.is_empty() {
-            for (id, car_name) in CARS {
-                let car_name_compare = car_name.to_lowercase();
-
-                let mut is_it = true;
-                for key in &keywords {
-                    let key = key.trim().to_lowercase();
-
-                    if !car_name_compare.contains(key.as_str()) {
-                        is_it = false;
-                        break;
-                    }
-                }
-
-                if is_it {
-                    info!("Found car {} ({})", car_name, id);
-                    return Some(id);
-                }
+        return ExitCode::SUCCESS;
+    }
+    if args.list_tracks {
+        println!("All Tracks:");
+        for item in catalog.tracks.iter() {
+            let names = catalog.aliases.track_aliases(item);
+            if names.is_empty() {
+                println!("{}", item);
+            } else {
+                println!("{} (aliases: {})", item, names.join(", "));
             }
         }
-
-        error!("Unable to parse car model '{}', skipping", text);
-        return None;
+        return ExitCode::SUCCESS;
     }
 
-    None
-}
-
-pub fn get_car_name_from_id(car_id: u32) -> Option<String> {
-    // Couldn't we put all ids and names into a map? Yes, but considering that we have only about 50, this is not a performance issue
-    for (id, name) in CARS {
-        if id == car_id {
-            return Some(name.to_string());
+    // comparing two bop.json files
+    if let [old, new] = args.diff.as_slice() {
+        let bops = read_bop(&PathBuf::from(old)).and_then(|old| {
+            let new = read_bop(&PathBuf::from(new))?;
+            Ok((old, new))
+        });
+        let (old, new) = match bops {
+            Ok(bops) => bops,
+            Err(e) => {
+                error!("Unable to read bop: {}, exiting...", e);
+                return ExitCode::from(EXIT_ERROR);
+            }
+        };
+
+        let changes = diff_bops(&old, &new, &catalog);
+        match args.diff_format {
+            DiffFormat::Text => print!("{}", diff::format_text(&changes)),
+            DiffFormat::Markdown => print!("{}", diff::format_markdown(&changes)),
+            DiffFormat::Json => match diff::format_json(&changes) {
+                Ok(json) => println!("{}", json),
+                Err(e) => {
+                    error!("Failed to produce diff: {}", e);
+                    return ExitCode::from(EXIT_ERROR);
+                }
+            },
         }
+        return ExitCode::SUCCESS;
     }
 
-    None
-}
-
-pub fn bop2csv(bop_json: String, output: Option<String>) -> Option<()> {
-    let path = PathBuf::from_str(&bop_json).ok()?;
+    // applying a patch
+    if let [patch, bop] = args.apply.as_slice() {
+        let patched = Patch::load(&PathBuf::from(patch)).and_then(|patch| {
+            let mut bop = read_bop(&PathBuf::from(bop))?;
+            apply_patch(&mut bop, &patch, &catalog)?;
+            Ok(bop)
+        });
+        let bop = match patched {
+            Ok(bop) => bop,
+            Err(e) => {
+                error!("Unable to apply patch: {}, exiting...", e);
+                return ExitCode::from(EXIT_ERROR);
+            }
+        };
 
-    if !path.is_file() {
-        error!("bop.json File does not exist!");
-        return None;
+        let path = PathBuf::from(args.output.unwrap_or("bop.json".to_string()));
+        if let Err(e) = write_bop(&bop, path, args.overwrite.into(), args.encoding.into()) {
+            error!("Unable to Save: {}, Exiting...", e);
+            return ExitCode::from(EXIT_ERROR);
+        }
+        return ExitCode::SUCCESS;
     }
 
-    info!("Reading File...");
-
-    let raw = fs::read(bop_json).ok()?;
-    let content = String::from_utf8(raw).ok()?;
-    let content = content.replace("\u{0}", "");
-
-
-    trace!("Parsing File...");
-
-    let entries: BOP = serde_json::from_str(content.as_str()).ok()?;
-    let entries = entries.entries;
-
-    trace!("Finished Parsing json, converting to table...");
-
-    // Parsing the entries into a table
-    let mut table: HashMap<String, Vec<Option<Entry>>> = HashMap::new();
-    let mut row_label: Vec<u32> = vec![];
-
-    for item in entries {
-        let mut row_index = 0;
-        for i in row_label.iter() {
-            if i == &item.car_model {
-                break;
+    // merging bop.json files
+    if !args.merge.is_empty() {
+        let mut diagnostics = Diagnostics::new(args.strict);
+        let paths: Vec<PathBuf> = args.merge.iter().map(PathBuf::from).collect();
+        let bop = match merge_bops(&paths, csv_options.duplicates, &catalog, &mut diagnostics) {
+            Ok(bop) => bop,
+            Err(e) => {
+                error!("Unable to merge: {}, exiting...", e);
+                return report(&diagnostics, args.report_format, false);
             }
-            row_index += 1;
-        }
-        if row_index == row_label.len() {
-            row_label.push(item.car_model);
-        }
+        };
 
-        if !table.contains_key(&item.track) {
-            table.insert(item.track.clone(), Vec::<Option<Entry>>::with_capacity(row_label.len() + 1));
+        let path = PathBuf::from(args.output.unwrap_or("bop.json".to_string()));
+        if let Err(e) = write_bop(&bop, path, args.overwrite.into(), args.encoding.into()) {
+            error!("Unable to Save: {}, Exiting...", e);
+            return report(&diagnostics, args.report_format, false);
         }
+        return report(&diagnostics, args.report_format, true);
+    }
 
-        let column = table.get_mut(&item.track).expect("We insured it exists by creating one if they don't");
-        while column.len() <= row_index {
-            column.push(None);
+    // bop 2 csv
+    if let Some(bop) = args.json {
+        if let Err(e) = bop2csv(
+            bop,
+            args.output,
+            args.overwrite.into(),
+            csv_options,
+            &catalog,
+        ) {
+            error!("Failed to parse bop to csv: {}, exiting...", e);
+            return ExitCode::from(EXIT_ERROR);
         }
-
-        column[row_index] = Some(item);
+        info!("Finished Writing");
+        return ExitCode::SUCCESS;
     }
 
-    // Column headers
-    let mut column_headers = Vec::<String>::with_capacity(23);
-    for (c, _) in table.iter() {
-        column_headers.push(c.to_string());
+    // Verifying that ballast path is present
+    if args.ballast.is_empty() {
+        error!("Ballast is required! See --help for futher info");
+        return ExitCode::from(EXIT_ERROR);
     }
-    column_headers.sort();
 
-    // Writing down the entries into Rows
-    let mut rows = Vec::<Vec<Option<Entry>>>::with_capacity(row_label.len());
-    let mut row_human_label = Vec::<String>::with_capacity(row_label.len());
-    for row in row_label {
-        row_human_label.push(get_car_name_from_id(row).unwrap_or(row.to_string()));
+    // Setting output file
+    let path = PathBuf::from(if let Some(target) = args.output {
+        target
+    } else {
+        "bop.json".to_string()
+    });
 
-        let index = rows.len();
-        let mut row_items = Vec::<Option<Entry>>::with_capacity(column_headers.len());
+    if path.is_dir() {
+        error!("Output path is a folder, please point at a File!");
+        return ExitCode::from(EXIT_ERROR);
+    }
 
-        for col in column_headers.iter() {
-            let col = &table[col];
-            if col.len() > index {
-                row_items.push((&col[index]).clone());
-            } else {
-                row_items.push(None);
+    let mut diagnostics = Diagnostics::new(args.strict);
+
+    // Applying the ballast and then the restrictor files in order, each on top of the ones before
+    let layers = args
+        .ballast
+        .into_iter()
+        .map(|layer| (layer, BopType::Ballast, args.sheet.as_deref()))
+        .chain(
+            args.restrictor
+                .into_iter()
+                .map(|layer| (layer, BopType::Restrictor, args.restrictor_sheet.as_deref())),
+        );
+    let mut res = Vec::<Entry>::new();
+    for (layer, file_type, sheet) in layers {
+        let entries = match parse_file(
+            layer.path,
+            file_type,
+            csv_options,
+            sheet,
+            &catalog,
+            &mut diagnostics,
+        ) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!(
+                    "Unable to parse {} csv: {}, exiting...",
+                    file_type.to_string().to_lowercase(),
+                    e
+                );
+                return report(&diagnostics, args.report_format, false);
             }
-        }
+        };
 
-        rows.push(row_items);
-    }
-    
-    trace!("Finished Tableizing");
-
-    // Output path
-    let (ballast_path, restrictor_path) = if let Some(target) = output {
-        let mut target = PathBuf::from(target);
-
-        if target.is_dir() {
-            // User defined a folder... perfect, we just put both files into there
-            let mut restrictor = target.clone();
-            target.push("ballast.csv");
-            restrictor.push("restrictor.csv");
-            (target, restrictor)
-        } else {
-            // user defined a file, we will create a restrictor csv in the same folder
-            let mut restrictor = target.clone();
-            restrictor.set_file_name("restrictor.csv");
-            (target, restrictor)
+        // cells in the restrictor file can also contain a ballast (15kg/3%), so both are merged
+        if let Err(e) = apply_layer(&mut res, entries, layer.mode, &catalog, &mut diagnostics) {
+            error!("Unable to apply {}: {}, exiting...", file_type, e);
+            return report(&diagnostics, args.report_format, false);
         }
-    } else {
-        (PathBuf::from("ballast.csv".to_string()), PathBuf::from("restrictor.csv".to_string()))
-    };
-
-    write_csv(&column_headers, &row_human_label, &rows, ballast_path, BopType::Ballast)?;
-    write_csv(&column_headers, &row_human_label, &rows, restrictor_path, BopType::Restrictor)
-}
-
-fn write_csv(column_headers: &Vec<String>, row_human_label: &Vec<String>, rows: &Vec<Vec<Option<Entry>>>, path: PathBuf, file_type: BopType) -> Option<()> {
-    trace!("Producing csv table for {}", file_type.to_string());
-    // Write to file
-    let mut output = String::new();
-    for track in column_headers {
-        output.push(',');
-        output.push_str(track.as_str());
     }
-    output.push('\n');
-
-    let mut contains_anything = false;
-
-    for (row_header, row) in zip(row_human_label, rows) {
-        output.push_str(row_header.as_str());
-
-        for item in row {
-            output.push(',');
-
-            output.push_str(if let Some(val) = item {
-                match file_type {
-                    BopType::Ballast =>
-                        if let Some(ballast) = val.ballast_kg {
-                            contains_anything = true;
-                            ballast
-                        } else {
-                            0
-                        },
-                    BopType::Restrictor =>
-                        if let Some(restrictor) = val.restrictor {
-                            contains_anything = true;
-                            restrictor
-                        } else {
-                            0
-                        },
-                }
-                
-            } else {
-                0
-            }.to_string().as_str());
-        }
 
-        output.push('\n');
+    // Removing values of 0 (they only clear earlier layers) and entries with no bop adjustment
+    let entries = remove_unadjusted(res);
+
+    if let Err(e) = write_bop(
+        &BOP { entries },
+        path,
+        args.overwrite.into(),
+        args.encoding.into(),
+    ) {
+        error!("Unable to Save: {}, Exiting...", e);
+        return report(&diagnostics, args.report_format, false);
     }
 
-    if contains_anything {
-        info!("Writing {}... ", file_type.to_string());
-        if path.exists() {
-            if !Confirm::new()
-                .with_prompt(format!(
-                    "File {} already exists. Override?",
-                    path.clone().to_str().expect("it is a string")
-                ))
-                .default(false)
-                .interact()
-                .unwrap_or(false)
-            {
-                info!("Unable to Save, Exiting...");
-                return None;
-            }
-    
-            fs::remove_file(&path).ok()?;
-        }
+    report(&diagnostics, args.report_format, true)
+}
 
+// Prints the diagnostics and picks the exit code, errors win over warnings
+fn report(diagnostics: &Diagnostics, format: ReportFormat, written: bool) -> ExitCode {
+    match format {
+        ReportFormat::Text if !diagnostics.items.is_empty() => print!("{}", diagnostics),
+        ReportFormat::Text => (),
+        ReportFormat::Json => match diagnostics.to_json() {
+            Ok(json) => println!("{}", json),
+            Err(e) => error!("Failed to produce report: {}", e),
+        },
+    }
 
-        fs::write(path, output).ok()
-    } else {
-        trace!("Skipped writing {}, bop does not contain any changes to it", file_type.to_string());
-        Some(())
+    match diagnostics.severity() {
+        _ if !written => ExitCode::from(EXIT_ERROR),
+        Some(Severity::Error) => ExitCode::from(EXIT_ERROR),
+        Some(Severity::Warning) => ExitCode::from(EXIT_WARNING),
+        None => ExitCode::SUCCESS,
     }
 }
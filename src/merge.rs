@@ -0,0 +1,107 @@
+// Combining bop.json files, like the ones the GT3 and GT4 committees produce separately
+
+use std::{collections::HashMap, path::PathBuf};
+
+use log::info;
+
+use crate::{
+    data::{Entry, BOP},
+    diagnostics::{Action, Kind},
+    format_values, read_bop, sum_entries, Catalog, Diagnostics, DuplicatePolicy, Error, Result,
+};
+
+/// Merges the bop.json files in order, matching the entries by track and car.
+/// Values only one file sets are combined, files that set different values for the same car at the same track
+/// are recorded as conflicts in the diagnostics and the policy picks the value.
+pub fn merge_bops(
+    paths: &[PathBuf],
+    policy: DuplicatePolicy,
+    catalog: &Catalog,
+    diagnostics: &mut Diagnostics,
+) -> Result<BOP> {
+    let mut entries: Vec<Entry> = vec![];
+    // (track, car_model) -> (index in entries, file the values were taken from)
+    let mut seen: HashMap<(String, u32), (usize, String)> = HashMap::new();
+
+    for path in paths {
+        let file = path.display().to_string();
+        diagnostics.set_file(&file);
+        let bop = read_bop(path)?;
+        info!("Merging {} entries of {}", bop.entries.len(), file);
+
+        for item in bop.entries {
+            let key = (item.track.clone(), item.car_model);
+            let Some((index, first)) = seen.get(&key).cloned() else {
+                seen.insert(key, (entries.len(), file.clone()));
+                entries.push(item);
+                continue;
+            };
+
+            let existing = &mut entries[index];
+            let differs = |a: Option<i32>, b: Option<i32>| a.is_some() && b.is_some() && a != b;
+            if !differs(existing.ballast_kg, item.ballast_kg)
+                && !differs(existing.restrictor, item.restrictor)
+            {
+                existing.ballast_kg = existing.ballast_kg.or(item.ballast_kg);
+                existing.restrictor = existing.restrictor.or(item.restrictor);
+                continue;
+            }
+
+            let text = format_values(item.ballast_kg, item.restrictor).unwrap_or_default();
+            diagnostics.push(
+                Kind::Conflict,
+                (0, 0),
+                &text,
+                format!(
+                    "Car {} at track {} was set to {} by {}",
+                    catalog
+                        .get_car_name_from_id(item.car_model)
+                        .unwrap_or(item.car_model.to_string()),
+                    item.track,
+                    format_values(existing.ballast_kg, existing.restrictor).unwrap_or_default(),
+                    first
+                ),
+                match policy {
+                    DuplicatePolicy::Error => Action::Aborted,
+                    DuplicatePolicy::FirstWins => Action::Ignored,
+                    DuplicatePolicy::LastWins => Action::Replaced,
+                    DuplicatePolicy::Sum => Action::Summed,
+                },
+            )?;
+
+            match policy {
+                DuplicatePolicy::Error => return Err(Error::Conflict { file, text }),
+                DuplicatePolicy::FirstWins => {
+                    existing.ballast_kg = existing.ballast_kg.or(item.ballast_kg);
+                    existing.restrictor = existing.restrictor.or(item.restrictor);
+                }
+                DuplicatePolicy::LastWins => {
+                    existing.ballast_kg = item.ballast_kg.or(existing.ballast_kg);
+                    existing.restrictor = item.restrictor.or(existing.restrictor);
+                    seen.insert(key, (index, file.clone()));
+                }
+                DuplicatePolicy::Sum => {
+                    // Only the values that differ are added up, the ones the files agree on are kept
+                    let summand = Entry {
+                        ballast_kg: item
+                            .ballast_kg
+                            .filter(|_| differs(existing.ballast_kg, item.ballast_kg)),
+                        restrictor: item
+                            .restrictor
+                            .filter(|_| differs(existing.restrictor, item.restrictor)),
+                        ..item.clone()
+                    };
+                    existing.ballast_kg = existing.ballast_kg.or(item.ballast_kg);
+                    existing.restrictor = existing.restrictor.or(item.restrictor);
+                    let (ballast, restrictor) =
+                        sum_entries(existing, &summand, catalog, diagnostics)?;
+                    existing.ballast_kg = ballast;
+                    existing.restrictor = restrictor;
+                }
+            }
+        }
+    }
+    info!("Merged {} entries", entries.len());
+
+    Ok(BOP { entries })
+}
@@ -0,0 +1,74 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+
+use crate::{
+    data::BOP,
+    encoding::{self, TextEncoding},
+    Error, Result,
+};
+
+// What to do when a file we want to write already exists
+#[derive(Debug, Clone, Copy)]
+pub enum OverwritePolicy {
+    // Asks the callback, which returns if the file may be overwritten
+    Prompt(fn(&Path) -> bool),
+    Force,
+    Never,
+    // Renames the existing file to <name>.bak before writing
+    Backup,
+}
+
+/// Makes sure the path can be written to under the given policy.
+/// Returns Error::OutputExists if the file exists and we are not allowed to replace it.
+pub fn check_overwrite(path: &Path, policy: OverwritePolicy) -> Result<()> {
+    if path.is_dir() {
+        return Err(Error::OutputIsFolder(path.to_path_buf()));
+    }
+
+    if !path.exists() {
+        return Ok(());
+    }
+
+    match policy {
+        OverwritePolicy::Force => Ok(()),
+        OverwritePolicy::Never => Err(Error::OutputExists(path.to_path_buf())),
+        OverwritePolicy::Prompt(ask) => {
+            if ask(path) {
+                Ok(())
+            } else {
+                Err(Error::OutputExists(path.to_path_buf()))
+            }
+        }
+        OverwritePolicy::Backup => {
+            let backup = backup_path(path);
+            info!("Moving {} to {}", path.display(), backup.display());
+            fs::rename(path, backup)?;
+            Ok(())
+        }
+    }
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+pub fn write_bop(
+    bop: &BOP,
+    path: PathBuf,
+    overwrite: OverwritePolicy,
+    text_encoding: TextEncoding,
+) -> Result<()> {
+    check_overwrite(&path, overwrite)?;
+
+    let json = serde_json::to_string_pretty(bop)?;
+    fs::write(&path, encoding::encode(&json, text_encoding))?;
+    info!("Finished writing to {}", path.display());
+
+    Ok(())
+}
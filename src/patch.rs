@@ -0,0 +1,262 @@
+// Small changes to a bop.json, like "Ferrari 296 +5kg at Monza, no restrictor for the Bentley anywhere"
+
+use std::{collections::HashMap, path::PathBuf};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    class_selector,
+    data::{Entry, BOP},
+    did_you_mean, read_toml_or_json, CarClass, Catalog, Error, Result, MAX_BALLAST_KG,
+    MAX_RESTRICTOR,
+};
+
+// The operations are applied in order.
+// Unknown keys are rejected, a misspelled key would otherwise silently change what an operation does.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Patch {
+    #[serde(default)]
+    pub operations: Vec<Operation>,
+}
+
+// A missing track stands for every track, a missing car for every car.
+// The car can be anything a sheet accepts except for misspelled names, including class selectors like "@GT4".
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Operation {
+    // Sets the values, 0 removes one
+    Set {
+        track: Option<String>,
+        car: Option<String>,
+        ballast_kg: Option<i32>,
+        restrictor: Option<i32>,
+    },
+    // Adds to the values, missing values count as 0
+    Delta {
+        track: Option<String>,
+        car: Option<String>,
+        ballast_kg: Option<i32>,
+        restrictor: Option<i32>,
+    },
+    // Removes the value, or the whole entry if no value is given
+    Delete {
+        track: Option<String>,
+        car: Option<String>,
+        value: Option<Value>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Value {
+    BallastKg,
+    Restrictor,
+}
+
+impl Patch {
+    /// Loads a patch file (.toml, otherwise json).
+    /// The operations are only checked by apply_patch.
+    pub fn load(path: &PathBuf) -> Result<Patch> {
+        info!("Loading patch {}", path.display());
+        read_toml_or_json(path)
+    }
+}
+
+/// Applies the operations of the patch in order.
+/// Every operation is checked against the catalog and the limits of the values first,
+/// if any is invalid Error::InvalidPatch lists all problems and the bop is left unchanged.
+pub fn apply_patch(bop: &mut BOP, patch: &Patch, catalog: &Catalog) -> Result<()> {
+    let mut entries = bop.entries.clone();
+    let mut index: HashMap<(String, u32), usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| ((entry.track.clone(), entry.car_model), i))
+        .collect();
+    let mut problems = vec![];
+
+    for (number, operation) in patch.operations.iter().enumerate() {
+        // Counted from 1, like the operations in the file
+        let mut problem =
+            |reason: String| problems.push(format!("operation {}: {}", number + 1, reason));
+
+        let (track, car) = match operation {
+            Operation::Set { track, car, .. }
+            | Operation::Delta { track, car, .. }
+            | Operation::Delete { track, car, .. } => (track, car),
+        };
+        let tracks = match track {
+            Some(track) => match catalog.validate_track(track) {
+                Some(track) => vec![track],
+                None => {
+                    problem(format!(
+                        "unknown track '{}'{}",
+                        track,
+                        did_you_mean(&catalog.suggest_tracks(track))
+                    ));
+                    continue;
+                }
+            },
+            None => catalog.tracks.clone(),
+        };
+        let cars = match car {
+            Some(car) => match resolve_cars(car, catalog) {
+                Ok(cars) => cars,
+                Err(reason) => {
+                    problem(reason);
+                    continue;
+                }
+            },
+            None => catalog.cars.iter().map(|car| car.id).collect(),
+        };
+
+        match *operation {
+            Operation::Set {
+                ballast_kg,
+                restrictor,
+                ..
+            }
+            | Operation::Delta {
+                ballast_kg,
+                restrictor,
+                ..
+            } => {
+                let is_delta = matches!(operation, Operation::Delta { .. });
+                if ballast_kg.is_none() && restrictor.is_none() {
+                    problem("neither ballast_kg nor restrictor is given".to_string());
+                    continue;
+                }
+
+                for track in tracks.iter() {
+                    for car_model in cars.iter() {
+                        let key = (track.clone(), *car_model);
+                        let i = *index.entry(key).or_insert_with(|| {
+                            entries.push(Entry {
+                                track: track.clone(),
+                                car_model: *car_model,
+                                ballast_kg: None,
+                                restrictor: None,
+                            });
+                            entries.len() - 1
+                        });
+                        let entry = &mut entries[i];
+                        let car_name = catalog
+                            .get_car_name_from_id(*car_model)
+                            .unwrap_or(car_model.to_string());
+
+                        if let Some(delta) = ballast_kg {
+                            let value = if is_delta {
+                                entry.ballast_kg.unwrap_or(0).checked_add(delta)
+                            } else {
+                                Some(delta)
+                            };
+                            match value {
+                                Some(value) if (-MAX_BALLAST_KG..=MAX_BALLAST_KG).contains(&value) => {
+                                    entry.ballast_kg = (value != 0).then_some(value)
+                                }
+                                Some(value) => problem(format!(
+                                    "ballast for car {} at track {} would be {}kg, the limit is {}kg",
+                                    car_name, track, value, MAX_BALLAST_KG
+                                )),
+                                None => problem(format!(
+                                    "adding {}kg to the ballast for car {} at track {} overflows",
+                                    delta, car_name, track
+                                )),
+                            }
+                        }
+                        if let Some(delta) = restrictor {
+                            let value = if is_delta {
+                                entry.restrictor.unwrap_or(0).checked_add(delta)
+                            } else {
+                                Some(delta)
+                            };
+                            match value {
+                                Some(value) if (0..=MAX_RESTRICTOR).contains(&value) => {
+                                    entry.restrictor = (value != 0).then_some(value)
+                                }
+                                Some(value) => problem(format!(
+                                    "restrictor for car {} at track {} would be {}%, it has to be between 0% and {}%",
+                                    car_name, track, value, MAX_RESTRICTOR
+                                )),
+                                None => problem(format!(
+                                    "adding {}% to the restrictor for car {} at track {} overflows",
+                                    delta, car_name, track
+                                )),
+                            }
+                        }
+                    }
+                }
+            }
+            Operation::Delete { value, .. } => {
+                let mut count = 0;
+                for entry in entries.iter_mut() {
+                    let matches = (track.is_none() || tracks.contains(&entry.track))
+                        && (car.is_none() || cars.contains(&entry.car_model));
+                    if !matches {
+                        continue;
+                    }
+                    match value {
+                        Some(Value::BallastKg) => entry.ballast_kg = None,
+                        Some(Value::Restrictor) => entry.restrictor = None,
+                        None => {
+                            entry.ballast_kg = None;
+                            entry.restrictor = None;
+                        }
+                    }
+                    count += 1;
+                }
+                info!("Operation {} matched {} entries", number + 1, count);
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(Error::InvalidPatch {
+            reason: problems.join("; "),
+        });
+    }
+
+    // Removing entries with no bop adjustment
+    entries.retain(|entry| entry.ballast_kg.is_some() || entry.restrictor.is_some());
+    bop.entries = entries;
+    Ok(())
+}
+
+// The car ids a car of an operation stands for, patches have to name cars exactly
+fn resolve_cars(car: &str, catalog: &Catalog) -> std::result::Result<Vec<u32>, String> {
+    if let Some(selector) = class_selector(car) {
+        let class = CarClass::from_name(selector)
+            .ok_or_else(|| format!("unknown car class '{}'", selector))?;
+        return Ok(catalog.cars_of_class(class).map(|car| car.id).collect());
+    }
+
+    let candidates = catalog.find_car_models(car);
+    let name = |id: &u32| {
+        format!(
+            "{} ({})",
+            catalog.get_car_name_from_id(*id).unwrap_or_default(),
+            id
+        )
+    };
+    match candidates.as_slice() {
+        [id] => Ok(vec![*id]),
+        [] => {
+            let names: Vec<String> = catalog
+                .suggest_car_models(car)
+                .iter()
+                .map(|id| catalog.get_car_name_from_id(*id).unwrap_or_default())
+                .collect();
+            Err(format!(
+                "unknown car model '{}'{}",
+                car,
+                did_you_mean(&names)
+            ))
+        }
+        _ => Err(format!(
+            "car model '{}' matches {}, add the id in brackets to pick one",
+            car,
+            candidates.iter().map(name).collect::<Vec<_>>().join(", ")
+        )),
+    }
+}
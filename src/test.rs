@@ -6,6 +6,20 @@ fn clean_up() {
     }
 }
 
+// Creates an empty folder for a test, removing what an earlier run left behind
+fn test_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).expect("Clean up operation failed");
+    }
+    fs::create_dir(&dir).expect("Setup up of the test failed");
+    dir
+}
+
+fn entry(track: &str, car_model: u32, ballast_kg: Option<i32>, restrictor: Option<i32>) -> crate::Entry {
+    crate::Entry { track: track.to_string(), car_model, ballast_kg, restrictor }
+}
+
 #[test]
 fn simple_reparse_check() {
     // reparse check parses the sample-bop.json to a csv, then reparses into a bop.json
@@ -27,13 +41,16 @@ fn simple_reparse_check() {
     let test_csv_option = Some(test_csv_string.clone());
 
     // Running Functions to test
-    crate::bop2csv(sample.clone(), test_csv_option).expect("Failed to parse bop.json to csv");
+    crate::bop2csv(sample.clone(), test_csv_option, crate::OverwritePolicy::Never, crate::CsvOptions::default(), &crate::Catalog::default()).expect("Failed to parse bop.json to csv");
 
-    let output = crate::parse_csv(test_csv_string, crate::BopType::Ballast).expect("Failed to parse csv to entries");
+    let output = crate::parse_csv(test_csv_string, crate::BopType::Ballast, crate::CsvOptions::default(), &crate::Catalog::default(), &mut crate::Diagnostics::default()).expect("Failed to parse csv to entries");
 
     clean_up();
 
     
+    // Values of 0 are kept by the parser to clear earlier layers
+    let output = crate::remove_unadjusted(output);
+
     // Removing entries with no bop adjustment, like we do in main
     let mut entries = Vec::<crate::Entry>::with_capacity(output.len());
     for item in output {
@@ -62,4 +79,634 @@ fn simple_reparse_check() {
 
     assert!(entries.is_empty(), "There were more entries then there should be");
 
-}
\ No newline at end of file
+}
+
+#[test]
+fn missing_file_error() {
+    let res = crate::parse_csv("samples/does-not-exist.csv".to_string(), crate::BopType::Ballast, crate::CsvOptions::default(), &crate::Catalog::default(), &mut crate::Diagnostics::default());
+    assert!(matches!(res, Err(crate::Error::FileMissing(_))), "Missing file did not produce a FileMissing error");
+}
+
+
+#[test]
+fn overwrite_policies() {
+    let dir = test_dir("test-overwrite");
+
+    let target = dir.join("bop.json");
+    fs::write(&target, "old").expect("Setup up of the test failed");
+    let bop = crate::BOP { entries: vec![] };
+
+    let never = crate::write_bop(&bop, target.clone(), crate::OverwritePolicy::Never, crate::encoding::TextEncoding::Utf8);
+    assert!(matches!(never, Err(crate::Error::OutputExists(_))), "Never policy overwrote the file");
+
+    let declined = crate::write_bop(&bop, target.clone(), crate::OverwritePolicy::Prompt(|_| false), crate::encoding::TextEncoding::Utf8);
+    assert!(matches!(declined, Err(crate::Error::OutputExists(_))), "Declined prompt overwrote the file");
+
+    crate::write_bop(&bop, target.clone(), crate::OverwritePolicy::Backup, crate::encoding::TextEncoding::Utf8).expect("Backup policy failed to write");
+    assert_eq!(fs::read_to_string(dir.join("bop.json.bak")).expect("Backup is missing"), "old");
+    assert_ne!(fs::read_to_string(&target).expect("Output is missing"), "old");
+
+    fs::remove_dir_all(&dir).expect("Clean up operation failed");
+}
+
+
+#[test]
+fn csv_quoting() {
+    let text = ",spa,\"monza\"\r\n\"Porsche 992, GT3R\",\"1\"\"0\",5\r\n\"Multi\nLine\",,\r\n\r\n";
+    let records = crate::csv::read_records(text, ',').expect("Failed to read csv");
+
+    assert_eq!(records.len(), 4);
+    assert_eq!(records[0], vec!["", "spa", "monza"]);
+    assert_eq!(records[1], vec!["Porsche 992, GT3R", "1\"0", "5"]);
+    assert_eq!(records[2], vec!["Multi\nLine", "", ""]);
+
+    let written = crate::csv::write_records(&records[..3], ',');
+    assert_eq!(crate::csv::read_records(&written, ',').expect("Failed to reread csv"), records[..3].to_vec());
+
+    assert!(matches!(crate::csv::read_records("a,\"b\n", ','), Err(crate::Error::UnterminatedQuote { row: 1 })));
+}
+
+
+#[test]
+fn csv_delimiter_detection() {
+    assert_eq!(crate::csv::detect_delimiter(";Spa;Monza\nAudi R8, Evo;1;2\n"), ';');
+    assert_eq!(crate::csv::detect_delimiter("\tSpa\tMonza\n"), '\t');
+    assert_eq!(crate::csv::detect_delimiter("\";a\",spa,monza\n"), ',');
+    assert_eq!(crate::csv::detect_delimiter(""), ',');
+}
+
+
+#[test]
+fn encoding_detection() {
+    let path = PathBuf::from("test.csv");
+    let text = ",spa\nCitroën,5\n";
+
+    let mut utf16le: Vec<u8> = text.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+    assert_eq!(crate::encoding::decode(&utf16le, &path).expect("Failed to decode UTF-16 LE"), text);
+    utf16le.splice(0..0, [0xFF, 0xFE]);
+    assert_eq!(crate::encoding::decode(&utf16le, &path).expect("Failed to decode UTF-16 LE with BOM"), text);
+
+    let mut utf16be: Vec<u8> = vec![0xFE, 0xFF];
+    utf16be.extend(text.encode_utf16().flat_map(|c| c.to_be_bytes()));
+    assert_eq!(crate::encoding::decode(&utf16be, &path).expect("Failed to decode UTF-16 BE"), text);
+
+    let mut utf8 = vec![0xEF, 0xBB, 0xBF];
+    utf8.extend(text.as_bytes());
+    assert_eq!(crate::encoding::decode(&utf8, &path).expect("Failed to decode UTF-8"), text);
+
+    let windows1252 = b",spa\nCitro\xEBn,5\n";
+    assert_eq!(crate::encoding::detect(windows1252), crate::encoding::TextEncoding::Windows1252);
+    assert_eq!(crate::encoding::decode(windows1252, &path).expect("Failed to decode Windows-1252"), text);
+
+    let json = "{\n  \"entries\": []\n}";
+    let utf16 = crate::encoding::encode(json, crate::encoding::TextEncoding::Utf16Le);
+    assert_eq!(&utf16[..4], &[b'{', 0, b'\n', 0]);
+    assert_eq!(crate::encoding::decode(&utf16, &path).expect("Failed to decode written UTF-16 LE"), json);
+}
+
+
+#[test]
+fn workbook_reparse_check() {
+    let dir = test_dir("test-workbook");
+
+    let sample_text = fs::read_to_string("samples/sample-bop.json").expect("Test Setup Failed: Failed to read sample-bop.json");
+    let sample_bop: crate::BOP = serde_json::from_str(sample_text.as_str()).expect("Test Setup Failed: Could not parse sample-bop.json into Entries");
+
+    for name in ["bop.ods", "bop.xlsx"] {
+        let workbook = dir.join(name).to_str().expect("it is a string").to_string();
+        crate::bop2csv("samples/sample-bop.json".to_string(), Some(workbook.clone()), crate::OverwritePolicy::Never, crate::CsvOptions::default(), &crate::Catalog::default()).expect("Failed to write bop.json to workbook");
+
+        let output = crate::parse_file(workbook.clone(), crate::BopType::Ballast, crate::CsvOptions::default(), None, &crate::Catalog::default(), &mut crate::Diagnostics::default()).expect("Failed to parse workbook to entries");
+        let missing_sheet = crate::parse_file(workbook, crate::BopType::Ballast, crate::CsvOptions::default(), Some("Nope"), &crate::Catalog::default(), &mut crate::Diagnostics::default());
+
+        assert!(matches!(missing_sheet, Err(crate::Error::SheetMissing { .. })), "Missing sheet was not reported for {}", name);
+
+        let entries: Vec<crate::Entry> = output.into_iter().filter(|item| item.ballast_kg.is_some()).collect();
+        for entry in sample_bop.entries.iter().filter(|item| item.ballast_kg.is_some()) {
+            assert!(entries.iter().any(|item| item.track == entry.track && item.car_model == entry.car_model && item.ballast_kg == entry.ballast_kg), "Failed to find sample-bop entry {} at {} in {}", entry.car_model, entry.track, name);
+        }
+    }
+
+    fs::remove_dir_all(&dir).expect("Clean up operation failed");
+}
+
+
+#[test]
+fn combined_cells() {
+    let table: Vec<Vec<String>> = vec![
+        vec!["".into(), "spa".into(), "monza".into(), "imola".into(), "zolder".into()],
+        vec!["Audi R8 Evo II".into(), "15kg/3%".into(), "5%".into(), "-7".into(), "1kg/2kg".into()],
+    ];
+    let entries = crate::parse_table(&table, crate::BopType::Combined, crate::CsvOptions::default(), &crate::Catalog::default(), &mut crate::Diagnostics::default()).expect("Failed to parse table");
+
+    assert_eq!(entries.len(), 3, "The cell with two weights should have been skipped");
+    assert_eq!((entries[0].ballast_kg, entries[0].restrictor), (Some(15), Some(3)));
+    assert_eq!((entries[1].ballast_kg, entries[1].restrictor), (None, Some(5)));
+    assert_eq!((entries[2].ballast_kg, entries[2].restrictor), (Some(-7), None));
+
+    let restrictor = crate::parse_table(&table[..], crate::BopType::Restrictor, crate::CsvOptions::default(), &crate::Catalog::default(), &mut crate::Diagnostics::default()).expect("Failed to parse table");
+    assert_eq!((restrictor[2].ballast_kg, restrictor[2].restrictor), (None, None), "Negative restrictors are dropped");
+}
+
+
+#[test]
+fn long_format() {
+    let table: Vec<Vec<String>> = vec![
+        vec!["Car".into(), "Track".into(), "ballast_kg".into(), "restrictor".into()],
+        vec!["Bentley 2018".into(), "Bathurst".into(), "12".into(), "".into()],
+        vec!["32".into(), "monza".into(), "".into(), "4".into()],
+        vec!["Nope".into(), "spa".into(), "1".into(), "1".into()],
+        vec!["32".into(), "spa".into(), "abc".into(), "5".into()],
+    ];
+    assert_eq!(crate::TableFormat::detect(&table, &crate::Catalog::default()), crate::TableFormat::Long);
+
+    let entries = crate::parse_long_table(&table, crate::CsvOptions::default(), &crate::Catalog::default(), &mut crate::Diagnostics::default()).expect("Failed to parse table");
+    assert_eq!(
+        entries,
+        vec![
+            crate::Entry { track: "mount_panorama".into(), car_model: 8, ballast_kg: Some(12), restrictor: None },
+            crate::Entry { track: "monza".into(), car_model: 32, ballast_kg: None, restrictor: Some(4) },
+            crate::Entry { track: "spa".into(), car_model: 32, ballast_kg: None, restrictor: Some(5) },
+        ]
+    );
+}
+
+
+#[test]
+fn transposed_matrix() {
+    let table: Vec<Vec<String>> = vec![
+        vec!["".into(), "Audi R8 Evo II".into(), "Bentley 2018".into()],
+        vec!["spa".into(), "5".into(), "-3".into()],
+        vec!["Bathurst".into(), "".into(), "10".into()],
+    ];
+    assert_eq!(crate::TableFormat::detect(&table, &crate::Catalog::default()), crate::TableFormat::Transposed);
+    assert_eq!(crate::TableFormat::detect(&crate::transpose(&table), &crate::Catalog::default()), crate::TableFormat::Matrix);
+
+    let options = crate::CsvOptions { format: Some(crate::TableFormat::Transposed), ..Default::default() };
+    let entries: Vec<crate::Entry> = crate::read_table(&table, crate::BopType::Ballast, options, &crate::Catalog::default(), &mut crate::Diagnostics::default()).expect("Failed to parse table").into_iter().filter(|item| item.ballast_kg.is_some()).collect();
+    assert_eq!(entries, crate::parse_table(&crate::transpose(&table), crate::BopType::Ballast, crate::CsvOptions::default(), &crate::Catalog::default(), &mut crate::Diagnostics::default()).expect("Failed to parse table").into_iter().filter(|item| item.ballast_kg.is_some()).collect::<Vec<_>>());
+    assert_eq!(entries.len(), 3);
+}
+
+
+#[test]
+fn diagnostics_positions() {
+    let table: Vec<Vec<String>> = vec![
+        vec!["".into(), "spa".into(), "Nowhere".into(), "monza".into()],
+        vec!["Audi R8 Evo II".into(), "55".into(), "1".into(), "abc".into()],
+        vec!["Nope".into(), "1".into(), "1".into(), "1".into()],
+    ];
+
+    let mut diagnostics = crate::Diagnostics::default();
+    diagnostics.set_file("test.csv");
+    let entries = crate::parse_table(&table, crate::BopType::Ballast, crate::CsvOptions::default(), &crate::Catalog::default(), &mut diagnostics).expect("Failed to parse table");
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].ballast_kg, Some(40));
+
+    let found: Vec<(crate::diagnostics::Kind, usize, usize, crate::diagnostics::Action)> = diagnostics.items.iter().map(|item| (item.kind, item.row, item.column, item.action)).collect();
+    assert_eq!(
+        found,
+        vec![
+            (crate::diagnostics::Kind::UnknownTrack, 1, 3, crate::diagnostics::Action::SkippedColumn),
+            (crate::diagnostics::Kind::OutOfRange, 2, 2, crate::diagnostics::Action::Clamped(40)),
+            (crate::diagnostics::Kind::UnparsableCell, 2, 4, crate::diagnostics::Action::SkippedCell),
+            (crate::diagnostics::Kind::UnknownCar, 3, 1, crate::diagnostics::Action::SkippedRow),
+        ]
+    );
+    assert_eq!(diagnostics.severity(), Some(crate::diagnostics::Severity::Error));
+
+    let json: serde_json::Value = serde_json::from_str(diagnostics.to_json().expect("Failed to serialize report").as_str()).expect("Report is not valid json");
+    assert_eq!(json["errors"], 3);
+    assert_eq!(json["warnings"], 1);
+    assert_eq!(json["diagnostics"][1]["kind"], "out_of_range");
+    assert_eq!(json["diagnostics"][1]["row"], 2);
+    assert_eq!(json["diagnostics"][1]["action"]["type"], "clamped");
+    assert_eq!(json["diagnostics"][1]["action"]["value"], 40);
+
+    let mut strict = crate::Diagnostics::new(true);
+    let res = crate::parse_table(&table, crate::BopType::Ballast, crate::CsvOptions::default(), &crate::Catalog::default(), &mut strict);
+    assert!(matches!(res, Err(crate::Error::UnknownTrack { row: 1, column: 3, .. })), "Strict mode did not abort on the first problem");
+}
+
+
+#[test]
+fn duplicate_entries() {
+    let table: Vec<Vec<String>> = vec![
+        vec!["".into(), "Bathurst".into(), "spa".into(), "Mount Panorama".into()],
+        vec!["Audi R8 Evo II".into(), "10".into(), "".into(), "35".into()],
+        vec!["Audi R8 Evo II".into(), "".into(), "5".into(), "".into()],
+    ];
+    let parse = |duplicates: crate::DuplicatePolicy, diagnostics: &mut crate::Diagnostics| {
+        let options = crate::CsvOptions { duplicates, ..Default::default() };
+        crate::parse_table(&table, crate::BopType::Ballast, options, &crate::Catalog::default(), diagnostics)
+    };
+    let mount_panorama = |entries: &Vec<crate::data::Entry>| -> Vec<Option<i32>> {
+        entries.iter().filter(|item| item.track == "mount_panorama").map(|item| item.ballast_kg).collect()
+    };
+
+    let mut diagnostics = crate::Diagnostics::default();
+    let entries = parse(crate::DuplicatePolicy::LastWins, &mut diagnostics).expect("Failed to parse table");
+    assert_eq!(mount_panorama(&entries), vec![Some(35)]);
+    assert_eq!(entries.iter().filter(|item| item.track == "spa").map(|item| item.ballast_kg).collect::<Vec<_>>(), vec![Some(5)], "Empty cells are no duplicates");
+    assert_eq!(diagnostics.items.len(), 1);
+    assert_eq!((diagnostics.items[0].kind, diagnostics.items[0].row, diagnostics.items[0].column), (crate::diagnostics::Kind::Duplicate, 2, 4));
+    assert!(diagnostics.items[0].message.contains("row 2, column 2"), "The first position is missing in the message");
+    assert_eq!(diagnostics.severity(), Some(crate::diagnostics::Severity::Warning));
+
+    let entries = parse(crate::DuplicatePolicy::FirstWins, &mut crate::Diagnostics::default()).expect("Failed to parse table");
+    assert_eq!(mount_panorama(&entries), vec![Some(10)]);
+
+    let mut diagnostics = crate::Diagnostics::default();
+    let entries = parse(crate::DuplicatePolicy::Sum, &mut diagnostics).expect("Failed to parse table");
+    assert_eq!(mount_panorama(&entries), vec![Some(40)], "The sum should be clamped");
+    assert_eq!(diagnostics.items.last().map(|item| item.action), Some(crate::diagnostics::Action::Clamped(40)));
+
+    let res = parse(crate::DuplicatePolicy::Error, &mut crate::Diagnostics::default());
+    assert!(matches!(res, Err(crate::Error::Duplicate { row: 2, column: 4, .. })));
+
+    // A 0 clears the value, so it is a duplicate like any other value
+    for (first, second) in [("0", "5"), ("5", "0")] {
+        let table: Vec<Vec<String>> = vec![vec!["".into(), "spa".into()], vec!["BMW M4 GT3".into(), first.into()], vec!["BMW M4 GT3".into(), second.into()]];
+        let parse = |duplicates: crate::DuplicatePolicy, diagnostics: &mut crate::Diagnostics| {
+            let options = crate::CsvOptions { duplicates, ..Default::default() };
+            crate::parse_table(&table, crate::BopType::Ballast, options, &crate::Catalog::default(), diagnostics).map(|entries| entries.iter().map(|item| item.ballast_kg).collect::<Vec<_>>())
+        };
+        let value = |text: &str| text.parse::<i32>().ok();
+
+        let mut diagnostics = crate::Diagnostics::default();
+        assert_eq!(parse(crate::DuplicatePolicy::FirstWins, &mut diagnostics).expect("Failed to parse table"), vec![value(first)]);
+        assert_eq!(diagnostics.items.iter().map(|item| (item.kind, item.row)).collect::<Vec<_>>(), vec![(crate::diagnostics::Kind::Duplicate, 3)], "A 0 against {} was not reported", first);
+        assert_eq!(parse(crate::DuplicatePolicy::LastWins, &mut crate::Diagnostics::default()).expect("Failed to parse table"), vec![value(second)]);
+        assert_eq!(parse(crate::DuplicatePolicy::Sum, &mut crate::Diagnostics::default()).expect("Failed to parse table"), vec![Some(5)]);
+        assert!(matches!(parse(crate::DuplicatePolicy::Error, &mut crate::Diagnostics::default()), Err(crate::Error::Duplicate { row: 3, column: 2, .. })));
+    }
+}
+
+
+#[test]
+fn ambiguous_car_models() {
+    // Every name we write has to be read back as the same car
+    let catalog = crate::Catalog::default();
+    for car in catalog.cars.iter() {
+        assert_eq!(catalog.find_car_models(&car.name), vec![car.id], "{} is not unique", car.name);
+    }
+
+    assert_eq!(catalog.find_car_models("Audi R8 [19]"), vec![19]);
+    assert_eq!(catalog.find_car_models("Audi R8 [99]"), Vec::<u32>::new());
+    assert_eq!(catalog.find_car_models("Porsche 991"), vec![23, 0, 85, 9]);
+
+    let table: Vec<Vec<String>> = vec![
+        vec!["".into(), "spa".into()],
+        vec!["Audi R8".into(), "5".into()],
+    ];
+    let mut diagnostics = crate::Diagnostics::default();
+    let entries = crate::parse_table(&table, crate::BopType::Ballast, crate::CsvOptions::default(), &crate::Catalog::default(), &mut diagnostics).expect("Failed to parse table");
+    assert_eq!(entries[0].car_model, 31, "The first candidate should be used");
+    assert_eq!((diagnostics.items[0].kind, diagnostics.items[0].action), (crate::diagnostics::Kind::AmbiguousCar, crate::diagnostics::Action::Matched(31)));
+    assert!(diagnostics.items[0].message.contains("Audi R8 LMS GT3 Evo 2019 (19)"));
+
+    let mut strict = crate::Diagnostics::new(true);
+    let res = crate::parse_table(&table, crate::BopType::Ballast, crate::CsvOptions::default(), &crate::Catalog::default(), &mut strict);
+    assert!(matches!(res, Err(crate::Error::AmbiguousCar { row: 2, column: 1, .. })));
+    assert_eq!(strict.items.last().map(|item| (item.severity, item.action)), Some((crate::diagnostics::Severity::Error, crate::diagnostics::Action::Aborted)), "Strict mode has to report the warning that stopped it as an error");
+}
+
+
+#[test]
+fn typo_tolerance() {
+    assert_eq!(crate::fuzzy::distance("zandvort", "zandvoort"), 1);
+    assert_eq!(crate::fuzzy::distance("kitten", "sitting"), 3);
+
+    let catalog = crate::Catalog::default();
+    assert_eq!(catalog.correct_track("Silverston").as_deref(), Some("silverstone"));
+    assert_eq!(catalog.correct_track("Zandvort").as_deref(), Some("zandvoort"));
+    assert_eq!(catalog.correct_track("Kylamy"), None, "Too many typos to be sure");
+    assert_eq!(catalog.suggest_tracks("Kylamy"), vec!["kyalami".to_string()]);
+    assert_eq!(catalog.correct_car_model("Lamborgini Huaracan 2015").as_deref(), Some("lamborghini huaracan 2015"));
+    assert_eq!(catalog.correct_car_model("Bentley 2018"), None, "Nothing to correct");
+
+    let table: Vec<Vec<String>> = vec![
+        vec!["".into(), "Silverston".into(), "Kylamy".into()],
+        vec!["Bentlee 2018".into(), "5".into(), "5".into()],
+    ];
+    let mut diagnostics = crate::Diagnostics::default();
+    let entries = crate::parse_table(&table, crate::BopType::Ballast, crate::CsvOptions::default(), &crate::Catalog::default(), &mut diagnostics).expect("Failed to parse table");
+    assert_eq!(entries.len(), 1);
+    assert_eq!((entries[0].track.as_str(), entries[0].car_model), ("silverstone", 8));
+
+    let found: Vec<(crate::diagnostics::Kind, usize, usize)> = diagnostics.items.iter().map(|item| (item.kind, item.row, item.column)).collect();
+    assert_eq!(
+        found,
+        vec![
+            (crate::diagnostics::Kind::Misspelled, 1, 2),
+            (crate::diagnostics::Kind::UnknownTrack, 1, 3),
+            (crate::diagnostics::Kind::Misspelled, 2, 1),
+        ]
+    );
+    assert!(diagnostics.items[1].message.contains("did you mean kyalami"));
+}
+
+
+#[test]
+fn aliases_file() {
+    let dir = test_dir("test-aliases");
+
+    let mut catalog = crate::Catalog::default();
+    let aliases = crate::Aliases::load(&PathBuf::from("samples/aliases-sample.toml")).expect("Failed to load sample aliases");
+    catalog.add_aliases(aliases).expect("Sample aliases are invalid");
+    assert_eq!(catalog.validate_track("ring").as_deref(), Some("nurburgring"));
+    assert_eq!(catalog.validate_track("Nordschleife").as_deref(), Some("nurburgring_24h"), "Built-in aliases should stay");
+    assert_eq!(catalog.find_car_models("merc  evo"), vec![25]);
+    assert_eq!(catalog.find_car_models("M4"), vec![30], "The alias should win over the name match");
+    assert_eq!(catalog.find_car_models("992"), vec![34], "Aliases are checked before ids");
+    assert_eq!(catalog.aliases.car_aliases(30), vec!["m4"]);
+
+    let json = dir.join("aliases.json");
+    fs::write(&json, r#"{"tracks": {"Ring": "nurburgring"}, "cars": {"Lambo": 33}}"#).expect("Failed to write aliases");
+    let aliases = crate::Aliases::load(&json).expect("Failed to load json aliases");
+    catalog.add_aliases(aliases).expect("Aliases are invalid");
+    assert_eq!(catalog.find_car_models("lambo"), vec![33]);
+
+    fs::write(&json, r#"{"tracks": {"Ring": "nowhere"}}"#).expect("Failed to write aliases");
+    let aliases = crate::Aliases::load(&json).expect("Failed to load json aliases");
+    assert!(matches!(catalog.add_aliases(aliases), Err(crate::Error::InvalidAlias { .. })));
+
+    fs::remove_dir_all(&dir).expect("Clean up operation failed");
+}
+
+
+#[test]
+fn custom_catalog() {
+    let dir = test_dir("test-catalog");
+
+    let builtin = crate::Catalog::default();
+    assert_eq!(builtin.validate_track("Bathurst").as_deref(), Some("mount_panorama"));
+    assert_eq!(builtin.get_car_name_from_id(50).as_deref(), Some("Alpine A110 GT4"));
+
+    let path = dir.join("catalog.toml");
+    fs::write(&path, r#"
+version = "2.0"
+tracks = ["spa", "New Track"]
+
+[[cars]]
+id = 90
+name = "Future Car GT3"
+class = "GT3"
+
+[aliases.tracks]
+"Francorchamps" = "spa"
+"#).expect("Failed to write catalog");
+    let catalog = crate::Catalog::load(&path).expect("Failed to load catalog");
+    assert_eq!(catalog.version, "2.0");
+    assert_eq!(catalog.validate_track("new track").as_deref(), Some("new_track"));
+    assert_eq!(catalog.validate_track("Francorchamps").as_deref(), Some("spa"));
+    assert_eq!(catalog.validate_track("monza"), None, "The catalog replaces the built-in one");
+    assert_eq!(catalog.validate_car_model(Some("Future")), Some(90));
+
+    let table: Vec<Vec<String>> = vec![
+        vec!["".into(), "New Track".into()],
+        vec!["Future Car".into(), "5".into()],
+    ];
+    let entries = crate::parse_table(&table, crate::BopType::Ballast, crate::CsvOptions::default(), &catalog, &mut crate::Diagnostics::default()).expect("Failed to parse table");
+    assert_eq!((entries[0].track.as_str(), entries[0].car_model), ("new_track", 90));
+
+    let path = dir.join("catalog.json");
+    fs::write(&path, r#"{"version": "2.0", "tracks": ["spa"], "cars": [{"id": 1, "name": "A", "class": "GT3"}, {"id": 1, "name": "B", "class": "GT4"}]}"#).expect("Failed to write catalog");
+    assert!(matches!(crate::Catalog::load(&path), Err(crate::Error::InvalidCatalog { .. })));
+
+    fs::remove_dir_all(&dir).expect("Clean up operation failed");
+}
+
+
+#[test]
+fn car_classes() {
+    let catalog = crate::Catalog::default();
+    for car in catalog.cars.iter() {
+        assert!(car.name.contains(&car.class.to_string()), "{} is not a {} car", car.name, car.class);
+    }
+    assert_eq!(catalog.get_car_class(27), Some(crate::CarClass::Tcx));
+    assert_eq!(catalog.cars_of_class(crate::CarClass::Gt4).count(), 11);
+
+    let table: Vec<Vec<String>> = vec![
+        vec!["".into(), "spa".into()],
+        vec!["Alpine A110".into(), "5".into()],
+        vec!["BMW M4 GT3".into(), "5".into()],
+    ];
+    let options = crate::CsvOptions { class: Some(crate::CarClass::Gt4), ..Default::default() };
+    let mut diagnostics = crate::Diagnostics::default();
+    let entries = crate::parse_table(&table, crate::BopType::Ballast, options, &catalog, &mut diagnostics).expect("Failed to parse table");
+    assert_eq!(entries.iter().map(|item| item.car_model).collect::<Vec<_>>(), vec![50]);
+    assert!(diagnostics.items.is_empty(), "Cars left out on purpose are no problem of the sheet");
+
+    let res = crate::parse_table(&table, crate::BopType::Ballast, options, &catalog, &mut crate::Diagnostics::new(true));
+    assert_eq!(res.expect("Strict mode must not abort on cars of other classes").len(), 1);
+}
+
+
+#[test]
+fn class_rows() {
+    let table: Vec<Vec<String>> = vec![
+        vec!["".into(), "spa".into(), "monza".into()],
+        vec!["BMW M4 GT4".into(), "5".into(), "".into()],
+        vec!["@GT4".into(), "10".into(), "20".into()],
+        vec!["Alpine A110".into(), "-5".into(), "".into()],
+        vec!["class:gt5".into(), "1".into(), "1".into()],
+    ];
+    let catalog = crate::Catalog::default();
+    let mut diagnostics = crate::Diagnostics::default();
+    let entries = crate::parse_table(&table, crate::BopType::Ballast, crate::CsvOptions::default(), &catalog, &mut diagnostics).expect("Failed to parse table");
+    let ballast = |track: &str, car_model: u32| entries.iter().find(|item| item.track == track && item.car_model == car_model).and_then(|item| item.ballast_kg);
+
+    assert_eq!(entries.iter().filter(|item| item.track == "spa").count(), catalog.cars_of_class(crate::CarClass::Gt4).count());
+    assert_eq!(ballast("spa", 55), Some(10));
+    assert_eq!(ballast("spa", 53), Some(5), "The car row has to win over the class row before it");
+    assert_eq!(ballast("spa", 50), Some(-5), "The car row has to win over the class row after it");
+    assert_eq!(ballast("monza", 50), Some(20), "Empty cells of a car row keep the class value");
+    assert_eq!(ballast("spa", 30), None);
+
+    let found: Vec<(crate::diagnostics::Kind, usize)> = diagnostics.items.iter().map(|item| (item.kind, item.row)).collect();
+    assert_eq!(found, vec![(crate::diagnostics::Kind::UnknownCar, 5)], "Overriding a class row is no duplicate");
+
+    let options = crate::CsvOptions { class: Some(crate::CarClass::Gt3), ..Default::default() };
+    let entries = crate::parse_table(&table[..3], crate::BopType::Ballast, options, &catalog, &mut crate::Diagnostics::default()).expect("Failed to parse table");
+    assert!(entries.is_empty(), "GT4 rows should be skipped when only GT3 cars are read");
+}
+
+
+#[test]
+fn default_column() {
+    let table: Vec<Vec<String>> = vec![
+        vec!["".into(), "spa".into(), "*".into(), "monza".into()],
+        vec!["BMW M4 GT3".into(), "5".into(), "10".into(), "0".into()],
+        vec!["@GT4".into(), "".into(), "3".into(), "".into()],
+        vec!["Alpine A110".into(), "".into(), "".into(), "1".into()],
+    ];
+    let catalog = crate::Catalog::default();
+    let mut diagnostics = crate::Diagnostics::default();
+    let entries = crate::parse_table(&table, crate::BopType::Ballast, crate::CsvOptions::default(), &catalog, &mut diagnostics).expect("Failed to parse table");
+    let ballast = |track: &str, car_model: u32| entries.iter().find(|item| item.track == track && item.car_model == car_model).and_then(|item| item.ballast_kg);
+
+    assert_eq!(entries.iter().filter(|item| item.car_model == 30).count(), catalog.tracks.len());
+    assert_eq!(ballast("spa", 30), Some(5), "The track column has to win over the default column before it");
+    assert_eq!(ballast("zolder", 30), Some(10));
+    assert_eq!(ballast("monza", 30), Some(0), "A 0 in the track column has to win over the default");
+    assert_eq!(ballast("zolder", 50), Some(3));
+    assert_eq!(ballast("monza", 50), Some(1));
+    assert!(diagnostics.items.is_empty(), "Overriding the default column is no duplicate");
+
+    let long: Vec<Vec<String>> = vec![
+        vec!["track".into(), "car".into(), "ballast_kg".into()],
+        vec!["default".into(), "BMW M4 GT3".into(), "7".into()],
+        vec!["monza".into(), "BMW M4 GT3".into(), "2".into()],
+    ];
+    let entries = crate::parse_long_table(&long, crate::CsvOptions::default(), &catalog, &mut crate::Diagnostics::default()).expect("Failed to parse table");
+    assert_eq!(entries.len(), catalog.tracks.len());
+    assert_eq!(entries.iter().find(|item| item.track == "monza").and_then(|item| item.ballast_kg), Some(2));
+}
+
+
+#[test]
+fn layers() {
+    let catalog = crate::Catalog::default();
+    let base = vec![entry("spa", 30, Some(10), None), entry("monza", 30, Some(20), Some(2))];
+
+    let mut entries = base.clone();
+    crate::apply_layer(&mut entries, vec![entry("spa", 30, None, Some(3)), entry("monza", 30, Some(5), Some(0)), entry("spa", 50, Some(1), None)], crate::LayerMode::Replace, &catalog, &mut crate::Diagnostics::default()).expect("Failed to apply layer");
+    assert_eq!(entries, vec![entry("spa", 30, Some(10), Some(3)), entry("monza", 30, Some(5), Some(0)), entry("spa", 50, Some(1), None)], "Empty values have to keep the earlier ones, a 0 has to clear them");
+
+    let mut entries = base.clone();
+    let mut diagnostics = crate::Diagnostics::default();
+    crate::apply_layer(&mut entries, vec![entry("spa", 30, Some(35), Some(1)), entry("monza", 30, Some(-20), None)], crate::LayerMode::Add, &catalog, &mut diagnostics).expect("Failed to apply layer");
+    assert_eq!(entries, vec![entry("spa", 30, Some(40), Some(1)), entry("monza", 30, Some(0), Some(2))]);
+    assert_eq!(diagnostics.items.iter().map(|item| (item.kind, item.row, item.action)).collect::<Vec<_>>(), vec![(crate::diagnostics::Kind::OutOfRange, 0, crate::diagnostics::Action::Clamped(40))]);
+}
+
+
+#[test]
+fn merge_files() {
+    let dir = test_dir("test-merge");
+
+    let gt3 = dir.join("gt3.json");
+    let gt4 = dir.join("gt4.json");
+    fs::write(&gt3, r#"{"entries": [{"track": "spa", "carModel": 30, "ballastKg": 10}, {"track": "monza", "carModel": 30, "restrictor": 2}]}"#).expect("Failed to write bop");
+    fs::write(&gt4, r#"{"entries": [{"track": "spa", "carModel": 30, "ballastKg": 15}, {"track": "monza", "carModel": 30, "ballastKg": 5}, {"track": "spa", "carModel": 50, "ballastKg": 5}]}"#).expect("Failed to write bop");
+    let paths = vec![gt3, gt4];
+    let catalog = crate::Catalog::default();
+    let spa = |bop: &crate::BOP| bop.entries.iter().find(|item| item.track == "spa" && item.car_model == 30).and_then(|item| item.ballast_kg);
+
+    let mut diagnostics = crate::Diagnostics::default();
+    let bop = crate::merge::merge_bops(&paths, crate::DuplicatePolicy::LastWins, &catalog, &mut diagnostics).expect("Failed to merge");
+    assert_eq!(bop.entries.len(), 3);
+    assert_eq!(spa(&bop), Some(15));
+    assert_eq!(bop.entries[1], crate::Entry { track: "monza".to_string(), car_model: 30, ballast_kg: Some(5), restrictor: Some(2) }, "Values of different files should be combined");
+    assert_eq!(diagnostics.items.iter().map(|item| (item.kind, item.action)).collect::<Vec<_>>(), vec![(crate::diagnostics::Kind::Conflict, crate::diagnostics::Action::Replaced)]);
+    assert!(diagnostics.items[0].message.contains("gt3.json"));
+
+    let bop = crate::merge::merge_bops(&paths, crate::DuplicatePolicy::FirstWins, &catalog, &mut crate::Diagnostics::default()).expect("Failed to merge");
+    assert_eq!(spa(&bop), Some(10));
+    let bop = crate::merge::merge_bops(&paths, crate::DuplicatePolicy::Sum, &catalog, &mut crate::Diagnostics::default()).expect("Failed to merge");
+    assert_eq!(spa(&bop), Some(25));
+
+    let restrictor = dir.join("restrictor.json");
+    fs::write(&restrictor, r#"{"entries": [{"track": "spa", "carModel": 30, "ballastKg": 10, "restrictor": 3}]}"#).expect("Failed to write bop");
+    let hotfix = dir.join("hotfix.json");
+    fs::write(&hotfix, r#"{"entries": [{"track": "spa", "carModel": 30, "ballastKg": 10, "restrictor": 5}]}"#).expect("Failed to write bop");
+    let bop = crate::merge::merge_bops(&[restrictor, hotfix], crate::DuplicatePolicy::Sum, &catalog, &mut crate::Diagnostics::default()).expect("Failed to merge");
+    assert_eq!(bop.entries[0], crate::Entry { track: "spa".to_string(), car_model: 30, ballast_kg: Some(10), restrictor: Some(8) }, "Only the restrictor conflicts, the ballast both files agree on must not be added up");
+
+    let huge = dir.join("huge.json");
+    fs::write(&huge, r#"{"entries": [{"track": "spa", "carModel": 30, "ballastKg": 2147483647}]}"#).expect("Failed to write bop");
+    let bop = crate::merge::merge_bops(&[huge, paths[0].clone()], crate::DuplicatePolicy::Sum, &catalog, &mut crate::Diagnostics::default()).expect("Failed to merge");
+    assert_eq!(spa(&bop), Some(40), "Sums of huge values have to be clamped instead of overflowing");
+    let res = crate::merge::merge_bops(&paths, crate::DuplicatePolicy::Error, &catalog, &mut crate::Diagnostics::default());
+    assert!(matches!(res, Err(crate::Error::Conflict { .. })));
+
+    fs::remove_dir_all(&dir).expect("Clean up operation failed");
+}
+
+
+#[test]
+fn diff_versions() {
+    let old = crate::BOP { entries: vec![entry("spa", 30, Some(10), None), entry("monza", 30, None, Some(2)), entry("spa", 7, Some(5), None), entry("zolder", 50, None, None)] };
+    let new = crate::BOP { entries: vec![entry("spa", 50, Some(5), None), entry("monza", 30, Some(5), Some(2)), entry("spa", 30, Some(10), None)] };
+
+    let changes = crate::diff::diff_bops(&old, &new, &crate::Catalog::default());
+    let found: Vec<(&str, &str, crate::diff::ChangeKind)> = changes.iter().map(|change| (change.track.as_str(), change.car.as_str(), change.change)).collect();
+    assert_eq!(
+        found,
+        vec![
+            ("monza", "BMW M4 GT3", crate::diff::ChangeKind::Changed),
+            ("spa", "Alpine A110 GT4", crate::diff::ChangeKind::Added),
+            ("spa", "BMW M6 GT3", crate::diff::ChangeKind::Removed),
+        ],
+        "Unchanged and empty entries should not show up"
+    );
+
+    assert_eq!(crate::diff::format_text(&changes).lines().nth(1), Some("  ~ BMW M4 GT3 (30): 2% -> 5kg/2%"));
+    assert!(crate::diff::format_markdown(&changes).contains("| Alpine A110 GT4 (50) | added | - | 5kg |"));
+    let json: serde_json::Value = serde_json::from_str(&crate::diff::format_json(&changes).expect("Failed to serialize diff")).expect("Diff is not valid json");
+    assert_eq!((json["added"].as_u64(), json["removed"].as_u64(), json["changed"].as_u64()), (Some(1), Some(1), Some(1)));
+    assert_eq!(json["changes"][0]["new"]["ballast_kg"], 5);
+    assert_eq!(crate::diff::format_text(&[]), "No changes\n");
+}
+
+
+#[test]
+fn patch_operations() {
+    let catalog = crate::Catalog::default();
+    let patch: crate::patch::Patch = toml::from_str(r#"
+[[operations]]
+op = "delta"
+track = "Monza"
+car = "Ferrari 296"
+ballast_kg = 5
+
+[[operations]]
+op = "set"
+track = "spa"
+car = "BMW M4 GT3"
+ballast_kg = 0
+restrictor = 3
+
+[[operations]]
+op = "delete"
+car = "Bentley Continental GT3 2018"
+value = "restrictor"
+"#).expect("Failed to parse patch");
+
+    let mut bop = crate::BOP { entries: vec![entry("monza", 32, Some(10), None), entry("spa", 30, Some(15), None), entry("spa", 8, Some(5), Some(2)), entry("monza", 8, None, Some(4))] };
+    crate::patch::apply_patch(&mut bop, &patch, &catalog).expect("Failed to apply patch");
+    assert_eq!(bop.entries, vec![entry("monza", 32, Some(15), None), entry("spa", 30, None, Some(3)), entry("spa", 8, Some(5), None)], "Entries without values should be removed");
+
+    let patch: crate::patch::Patch = serde_json::from_str(r#"{"operations": [
+        {"op": "set", "track": "Monz", "car": "Ferrari 296", "ballast_kg": 5},
+        {"op": "delta", "track": "monza", "car": "Ferrari 296", "ballast_kg": 30},
+        {"op": "set", "car": "Audi R8", "restrictor": 1},
+        {"op": "delete", "car": "@GT4"},
+        {"op": "delta", "track": "monza", "car": "Ferrari 296", "ballast_kg": 2147483647},
+        {"op": "set", "track": "monza", "car": "Ferrari 296", "ballast_kg": -2147483648}
+    ]}"#).expect("Failed to parse patch");
+    let before = bop.entries.clone();
+    let res = crate::patch::apply_patch(&mut bop, &patch, &catalog);
+    let Err(crate::Error::InvalidPatch { reason }) = res else {
+        panic!("The patch should be invalid");
+    };
+    assert!(reason.contains("operation 1: unknown track 'Monz', did you mean monza?"));
+    assert!(reason.contains("operation 2: ballast for car Ferrari 296 GT3 at track monza would be 45kg"));
+    assert!(reason.contains("operation 3: car model 'Audi R8' matches"));
+    assert!(!reason.contains("operation 4"));
+    assert!(reason.contains("operation 5: adding 2147483647kg to the ballast for car Ferrari 296 GT3 at track monza overflows"));
+    assert!(reason.contains("operation 6: ballast for car Ferrari 296 GT3 at track monza would be -2147483648kg"));
+    assert_eq!(bop.entries, before, "An invalid patch must not change anything");
+
+    let dir = test_dir("test-patch");
+    crate::patch::Patch::load(&PathBuf::from("samples/patch-sample.toml")).expect("Failed to load sample patch");
+    let json = dir.join("patch.json");
+    fs::write(&json, r#"{"operations": [{"op": "delete", "car": "BMW M4 GT3", "vaule": "restrictor"}]}"#).expect("Failed to write patch");
+    assert!(matches!(crate::patch::Patch::load(&json), Err(crate::Error::Json(_))), "A misspelled key must not delete the whole entry");
+    let toml = dir.join("patch.toml");
+    fs::write(&toml, "[[operations]]\nop = \"set\"\nballast_kg = 5\nrestrictr = 3\n").expect("Failed to write patch");
+    assert!(matches!(crate::patch::Patch::load(&toml), Err(crate::Error::Toml(_))), "A misspelled key must not be ignored");
+
+    fs::remove_dir_all(&dir).expect("Clean up operation failed");
+}
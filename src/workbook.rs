@@ -0,0 +1,183 @@
+// Reading and writing spreadsheet workbooks, so nobody has to export each sheet to csv by hand
+
+use std::{fs::File, io::Write, path::Path};
+
+use calamine::{Data, Reader};
+use log::{info, trace};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{BopType, Error, Result};
+
+pub fn is_workbook(path: &Path) -> bool {
+    is_extension(path, "ods") || is_extension(path, "xlsx")
+}
+
+fn is_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case(extension))
+        .unwrap_or(false)
+}
+
+/// Reads one sheet of the workbook into a table of cells.
+/// Without a sheet name we take the sheet named after the file_type (like "Ballast"), or the first one.
+pub fn read_sheet(
+    path: &Path,
+    sheet: Option<&str>,
+    file_type: BopType,
+) -> Result<Vec<Vec<String>>> {
+    if !path.is_file() {
+        return Err(Error::FileMissing(path.to_path_buf()));
+    }
+
+    // calamine picks the format based on the extension
+    let mut workbook = calamine::open_workbook_auto(path)?;
+    let names = workbook.sheet_names();
+
+    let name = match sheet {
+        Some(sheet) => find_sheet(&names, sheet).ok_or_else(|| Error::SheetMissing {
+            path: path.to_path_buf(),
+            sheet: sheet.to_string(),
+        })?,
+        None => find_sheet(&names, file_type.to_string().as_str())
+            .or_else(|| names.first().cloned())
+            .ok_or_else(|| Error::SheetMissing {
+                path: path.to_path_buf(),
+                sheet: file_type.to_string(),
+            })?,
+    };
+    info!("Reading sheet {}", name);
+
+    let range = workbook.worksheet_range(&name)?;
+
+    // The range only covers the used cells, so we have to pad it back to start at A1
+    let (start_row, start_col) = range.start().unwrap_or((0, 0));
+    let mut table = vec![Vec::<String>::new(); start_row as usize];
+    for row in range.rows() {
+        let mut cells = vec![String::new(); start_col as usize];
+        cells.extend(row.iter().map(cell_to_string));
+        table.push(cells);
+    }
+
+    Ok(table)
+}
+
+fn find_sheet(names: &[String], sheet: &str) -> Option<String> {
+    names
+        .iter()
+        .find(|name| name.as_str() == sheet)
+        .or_else(|| names.iter().find(|name| name.eq_ignore_ascii_case(sheet)))
+        .cloned()
+}
+
+fn cell_to_string(cell: &Data) -> String {
+    match cell {
+        Data::Empty => String::new(),
+        Data::String(text) => text.clone(),
+        Data::Int(value) => value.to_string(),
+        // Spreadsheets store every number as float
+        Data::Float(value) if value.fract() == 0.0 => (*value as i64).to_string(),
+        _ => cell.to_string(),
+    }
+}
+
+/// Writes the tables as sheets of a workbook, the format is based on the extension
+pub fn write_sheets(path: &Path, sheets: &[(String, Vec<Vec<String>>)]) -> Result<()> {
+    if is_extension(path, "xlsx") {
+        write_xlsx(path, sheets)
+    } else {
+        write_ods(path, sheets)
+    }
+}
+
+/// Writes the tables as sheets of an Excel workbook
+pub fn write_xlsx(path: &Path, sheets: &[(String, Vec<Vec<String>>)]) -> Result<()> {
+    trace!("Writing workbook {}", path.display());
+
+    let mut workbook = rust_xlsxwriter::Workbook::new();
+    for (name, table) in sheets {
+        let worksheet = workbook.add_worksheet();
+        worksheet.set_name(name)?;
+
+        for (row_index, row) in table.iter().enumerate() {
+            for (col_index, cell) in row.iter().enumerate() {
+                let (row_index, col_index) = (row_index as u32, col_index as u16);
+                if cell.is_empty() {
+                    continue;
+                } else if let Ok(value) = cell.parse::<i64>() {
+                    worksheet.write_number(row_index, col_index, value as f64)?;
+                } else {
+                    worksheet.write_string(row_index, col_index, cell)?;
+                }
+            }
+        }
+    }
+
+    workbook.save(path)?;
+    Ok(())
+}
+
+/// Writes the tables as sheets of an OpenDocument spreadsheet
+pub fn write_ods(path: &Path, sheets: &[(String, Vec<Vec<String>>)]) -> Result<()> {
+    trace!("Writing workbook {}", path.display());
+
+    let mut content = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" office:version="1.2"><office:body><office:spreadsheet>"#,
+    );
+    for (name, table) in sheets {
+        content.push_str(&format!(
+            r#"<table:table table:name="{}">"#,
+            escape_xml(name)
+        ));
+        for row in table {
+            content.push_str("<table:table-row>");
+            for cell in row {
+                if cell.is_empty() {
+                    content.push_str("<table:table-cell/>");
+                } else if cell.parse::<i64>().is_ok() {
+                    content.push_str(&format!(
+                        r#"<table:table-cell office:value-type="float" office:value="{0}"><text:p>{0}</text:p></table:table-cell>"#,
+                        cell
+                    ));
+                } else {
+                    content.push_str(&format!(
+                        r#"<table:table-cell office:value-type="string"><text:p>{}</text:p></table:table-cell>"#,
+                        escape_xml(cell)
+                    ));
+                }
+            }
+            content.push_str("</table:table-row>");
+        }
+        content.push_str("</table:table>");
+    }
+    content.push_str("</office:spreadsheet></office:body></office:document-content>");
+
+    let manifest = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.2">
+ <manifest:file-entry manifest:full-path="/" manifest:version="1.2" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/>
+ <manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>"#;
+
+    let mut zip = ZipWriter::new(File::create(path)?);
+    // The mimetype has to be the first file and uncompressed
+    zip.start_file(
+        "mimetype",
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+    )?;
+    zip.write_all(b"application/vnd.oasis.opendocument.spreadsheet")?;
+    zip.start_file("META-INF/manifest.xml", SimpleFileOptions::default())?;
+    zip.write_all(manifest.as_bytes())?;
+    zip.start_file("content.xml", SimpleFileOptions::default())?;
+    zip.write_all(content.as_bytes())?;
+    zip.finish()?;
+
+    Ok(())
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}